@@ -0,0 +1,70 @@
+use std::f64::consts::PI;
+
+use model::Weapon;
+
+// сколько направлений между текущим прицелом и целью проверяем
+const AIM_CANDIDATES: usize = 16;
+
+/// Выбранное направление прицела и вероятность попадания пулей в момент выстрела
+#[derive(Clone, Debug)]
+pub struct Aim {
+    pub angle: f64,
+    pub hit_chance: f64,
+}
+
+/// Разброс через ticks тиков после поворота прицела на turn:
+/// поворот сразу добавляется к разбросу (не больше max_spread), потом разброс уменьшается на aim_speed в секунду до min_spread
+pub fn spread_after(weapon: &Weapon, turn: f64, ticks: f64, ticks_per_second: f64) -> f64 {
+    let spread = (weapon.spread + turn.abs()).min(weapon.params.max_spread);
+    (spread - weapon.params.aim_speed * ticks / ticks_per_second).max(weapon.params.min_spread)
+}
+
+/// доля равномерного разброса [angle - spread, angle + spread], которая попадает в цель (target_angle +- target_half_angle)
+pub fn hit_chance(angle: f64, spread: f64, target_angle: f64, target_half_angle: f64) -> f64 {
+    let offset = delta_angle(target_angle, angle);
+    if spread <= 0.0 {
+        return if offset.abs() <= target_half_angle { 1.0 } else { 0.0 };
+    }
+    let lo = (offset - spread).max(-target_half_angle);
+    let hi = (offset + spread).min(target_half_angle);
+    ((hi - lo).max(0.0) / (2.0 * spread)).min(1.0)
+}
+
+/// Выбор прицела: направления от текущего до направления на цель равными шагами.
+/// Для каждого считаем разброс к моменту, когда оружие сможет выстрелить (через fire_ticks тиков),
+/// и берем максимальную вероятность попадания, при равной - меньший поворот (он меньше раскачивает прицел).
+pub fn choose_aim(weapon: &Weapon, target_angle: f64, target_half_angle: f64, fire_ticks: f64, ticks_per_second: f64) -> Aim {
+    let last_angle = match weapon.last_angle {
+        Some(last_angle) => last_angle,
+        None => {
+            let spread = spread_after(weapon, 0.0, fire_ticks, ticks_per_second);
+            return Aim { angle: target_angle, hit_chance: hit_chance(target_angle, spread, target_angle, target_half_angle) };
+        }
+    };
+    let delta = delta_angle(last_angle, target_angle);
+    let mut best = Aim { angle: last_angle, hit_chance: -1.0 };
+    for i in 0..=AIM_CANDIDATES {
+        let turn = delta * i as f64 / AIM_CANDIDATES as f64;
+        let angle = last_angle + turn;
+        let spread = spread_after(weapon, turn, fire_ticks, ticks_per_second);
+        let chance = hit_chance(angle, spread, target_angle, target_half_angle);
+        if chance > best.hit_chance + 1e-9 {
+            best = Aim { angle: normalize_angle(angle), hit_chance: chance };
+        }
+    }
+    best
+}
+
+pub fn delta_angle(angle_from: f64, angle_to: f64) -> f64 {
+    normalize_angle(angle_to - angle_from)
+}
+
+pub fn normalize_angle(mut angle: f64) -> f64 {
+    while angle > PI {
+        angle -= 2.0 * PI;
+    }
+    while angle < -PI {
+        angle += 2.0 * PI;
+    }
+    angle
+}
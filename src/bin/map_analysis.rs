@@ -0,0 +1,342 @@
+//! Анализ карты по графу ходов.
+//!
+//! Запуск: `cargo run --release --bin map_analysis -- <уровень> [<запись игры>]`
+//!
+//! Уровень - текстовый файл или запись игры (файл, который пишет бот при заданной переменной окружения
+//! AICUP_CAPTURE, берется первое сообщение). Из записи берутся карта со спавнами и лутом и параметры игры.
+//! Для текстового уровня параметры берутся из записи, если она задана, иначе параметры чемпионата.
+//! Текстовый уровень - строки сверху вниз:
+//! `#` стена, `.` пусто, `^` платформа, `H` лестница, `T` батут,
+//! `P` спавн юнита, `W` оружие, `+` аптечка, `M` мина (лут и спавны стоят на пустых клетках).
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use model::{Item, Level, Properties, Tile, Vec2F64};
+use trans::Trans;
+
+use aicup2019::path::{MoveType, Paths, TilePos, VerticalState};
+use aicup2019::properties::default_properties;
+
+struct MapInfo {
+    level: Level,
+    properties: Properties,
+    spawns: Vec<TilePos>,
+    loot: Vec<(TilePos, String)>,
+}
+
+fn tile_pos(position: &Vec2F64) -> TilePos {
+    (position.x as isize, position.y as isize)
+}
+
+fn loot_name(item: &Item) -> String {
+    match item {
+        Item::HealthPack { .. } => "HealthPack".to_owned(),
+        Item::Weapon { weapon_type } => format!("{:?}", weapon_type),
+        Item::Mine {} => "Mine".to_owned(),
+    }
+}
+
+fn load_capture(data: &[u8]) -> std::io::Result<MapInfo> {
+    let message = model::ServerMessageGame::read_from(data)?;
+    let game = message.player_view
+        .ok_or_else(|| std::io::Error::other("capture has no game"))?
+        .game;
+    Ok(MapInfo {
+        spawns: game.units.iter().map(|unit| tile_pos(&unit.position)).collect(),
+        loot: game.loot_boxes.iter().map(|loot| (tile_pos(&loot.position), loot_name(&loot.item))).collect(),
+        level: game.level,
+        properties: game.properties,
+    })
+}
+
+fn load_text(text: &str, properties: Properties) -> MapInfo {
+    let rows: Vec<&str> = text.lines().map(|line| line.trim_end()).filter(|line| !line.is_empty()).collect();
+    let height = rows.len();
+    let width = rows.iter().map(|row| row.len()).max().expect("Empty level");
+    let mut tiles = vec![vec![Tile::Wall; height]; width];
+    let mut spawns = Vec::new();
+    let mut loot = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        let y = height - 1 - i;
+        for (x, c) in row.chars().enumerate() {
+            let pos = (x as isize, y as isize);
+            tiles[x][y] = match c {
+                '#' => Tile::Wall,
+                '^' => Tile::Platform,
+                'H' => Tile::Ladder,
+                'T' => Tile::JumpPad,
+                _ => Tile::Empty,
+            };
+            match c {
+                'P' => spawns.push(pos),
+                'W' => loot.push((pos, "Weapon".to_owned())),
+                '+' => loot.push((pos, "HealthPack".to_owned())),
+                'M' => loot.push((pos, "Mine".to_owned())),
+                _ => {}
+            }
+        }
+    }
+    MapInfo {
+        level: Level { tiles },
+        properties,
+        spawns,
+        loot,
+    }
+}
+
+fn tile(level: &Level, (x, y): TilePos) -> Tile {
+    if x < 0 || y < 0 || x as usize >= level.width() || y as usize >= level.height() {
+        return Tile::Wall;
+    }
+    level.tiles[x as usize][y as usize].clone()
+}
+
+// клетка, в которой можно стоять: не стена, а снизу стена, платформа или лестница (или сама лестница)
+fn is_standable(level: &Level, pos: TilePos) -> bool {
+    let t = tile(level, pos);
+    if t == Tile::Wall || t == Tile::JumpPad {
+        return false;
+    }
+    if t == Tile::Ladder {
+        return true;
+    }
+    let below = tile(level, (pos.0, pos.1 - 1));
+    below == Tile::Wall || below == Tile::Platform || below == Tile::Ladder
+}
+
+/// Граф по клеткам: для каждой клетки множество соседних клеток, куда есть ход.
+/// `skip` - типы ходов, которые не учитываются.
+fn tile_graph(paths: &Paths, skip: &[MoveType]) -> BTreeMap<TilePos, BTreeSet<TilePos>> {
+    let mut graph: BTreeMap<TilePos, BTreeSet<TilePos>> = BTreeMap::new();
+    for moves in paths.outgoing.values() {
+        for mov in moves {
+            if skip.contains(&mov.typ) {
+                continue;
+            }
+            graph.entry(mov.pos1).or_default();
+            if mov.pos1 != mov.pos2 {
+                graph.entry(mov.pos1).or_default().insert(mov.pos2);
+            }
+            graph.entry(mov.pos2).or_default();
+        }
+    }
+    graph
+}
+
+/// Граф по клеткам, где можно стоять: полет через воздушные клетки стягивается в одно ребро.
+fn ground_graph(graph: &BTreeMap<TilePos, BTreeSet<TilePos>>, level: &Level) -> BTreeMap<TilePos, BTreeSet<TilePos>> {
+    let mut result = BTreeMap::new();
+    for &start in graph.keys().filter(|&&pos| is_standable(level, pos)) {
+        let mut next_ground = BTreeSet::new();
+        let mut visited = BTreeSet::new();
+        let mut queue: VecDeque<TilePos> = graph[&start].iter().cloned().collect();
+        while let Some(pos) = queue.pop_front() {
+            if !visited.insert(pos) {
+                continue;
+            }
+            if is_standable(level, pos) {
+                if pos != start {
+                    next_ground.insert(pos);
+                }
+                continue;
+            }
+            if let Some(next) = graph.get(&pos) {
+                queue.extend(next.iter().cloned());
+            }
+        }
+        result.insert(start, next_ground);
+    }
+    result
+}
+
+fn reachable(graph: &BTreeMap<TilePos, BTreeSet<TilePos>>, starts: &[TilePos], removed: Option<TilePos>) -> BTreeSet<TilePos> {
+    let mut visited = BTreeSet::new();
+    let mut queue: VecDeque<TilePos> = starts.iter().cloned().filter(|&pos| Some(pos) != removed).collect();
+    while let Some(pos) = queue.pop_front() {
+        if !visited.insert(pos) {
+            continue;
+        }
+        if let Some(next) = graph.get(&pos) {
+            next.iter()
+                .filter(|&&next_pos| Some(next_pos) != removed && !visited.contains(&next_pos))
+                .for_each(|&next_pos| queue.push_back(next_pos));
+        }
+    }
+    visited
+}
+
+// связные компоненты клеток (соседство по 8 направлениям)
+fn regions(tiles: &BTreeSet<TilePos>) -> Vec<Vec<TilePos>> {
+    let mut visited = BTreeSet::new();
+    let mut result = Vec::new();
+    for &start in tiles {
+        if visited.contains(&start) {
+            continue;
+        }
+        let mut region = Vec::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(start);
+        visited.insert(start);
+        while let Some((x, y)) = queue.pop_front() {
+            region.push((x, y));
+            for dx in -1..=1 {
+                for dy in -1..=1 {
+                    let next = (x + dx, y + dy);
+                    if tiles.contains(&next) && visited.insert(next) {
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        result.push(region);
+    }
+    result
+}
+
+fn format_tiles(tiles: &[TilePos]) -> String {
+    tiles.iter().map(|(x, y)| format!("({},{})", x, y)).collect::<Vec<_>>().join(" ")
+}
+
+fn print_map(info: &MapInfo, marks: &BTreeMap<TilePos, char>) {
+    let level = &info.level;
+    for y in (0..level.height()).rev() {
+        let row: String = (0..level.width())
+            .map(|x| {
+                let pos = (x as isize, y as isize);
+                if let Some(&mark) = marks.get(&pos) {
+                    return mark;
+                }
+                match level.tiles[x][y] {
+                    Tile::Wall => '#',
+                    Tile::Platform => '^',
+                    Tile::Ladder => 'H',
+                    Tile::JumpPad => 'T',
+                    Tile::Empty => '.',
+                }
+            })
+            .collect();
+        println!("{}", row);
+    }
+}
+
+fn main() {
+    let usage = "Usage: map_analysis <level file or capture> [<capture>]";
+    let data = std::fs::read(std::env::args().nth(1).expect(usage)).expect("Can't read level");
+    let info = match load_capture(&data) {
+        Ok(info) => info,
+        Err(_) => {
+            let properties = match std::env::args().nth(2) {
+                Some(capture) => {
+                    let capture = std::fs::read(capture).expect("Can't read capture");
+                    load_capture(&capture).expect("Can't parse capture").properties
+                }
+                None => default_properties(),
+            };
+            load_text(std::str::from_utf8(&data).expect("Level is neither a capture nor text"), properties)
+        }
+    };
+    if info.spawns.is_empty() {
+        println!("no spawns on the map");
+        return;
+    }
+
+    let mut paths = Paths::new();
+    for &spawn in &info.spawns {
+        paths.update_paths(spawn, VerticalState::Default, &info.level, &info.properties);
+    }
+
+    println!("== moves");
+    let mut move_counts: BTreeMap<String, usize> = BTreeMap::new();
+    for moves in paths.outgoing.values() {
+        for mov in moves {
+            *move_counts.entry(format!("{:?}", mov.typ)).or_default() += 1;
+        }
+    }
+    println!("nodes {} edges {}", paths.outgoing.len(), move_counts.values().sum::<usize>());
+    for (typ, count) in &move_counts {
+        println!("  {:<18} {}", typ, count);
+    }
+
+    let graph = tile_graph(&paths, &[]);
+    let reached = reachable(&graph, &info.spawns, None);
+
+    println!("== unreachable regions");
+    let unreachable: BTreeSet<TilePos> = (0..info.level.width() as isize)
+        .flat_map(|x| (0..info.level.height() as isize).map(move |y| (x, y)))
+        .filter(|&pos| is_standable(&info.level, pos) && !reached.contains(&pos))
+        .collect();
+    for region in regions(&unreachable) {
+        println!("  {} tiles: {}", region.len(), format_tiles(&region));
+    }
+
+    println!("== reachable only with jump pads");
+    let pad_moves = [
+        MoveType::PadJumpLeft,
+        MoveType::PadJumpRight,
+        MoveType::PadJump2Left,
+        MoveType::PadJump2Right,
+        MoveType::PadJumpUp,
+        MoveType::PadJumpStop,
+    ];
+    let reached_without_pads = reachable(&tile_graph(&paths, &pad_moves), &info.spawns, None);
+    let pad_only: Vec<TilePos> = reached.iter().cloned().filter(|pos| !reached_without_pads.contains(pos)).collect();
+    println!("  {}", format_tiles(&pad_only));
+
+    println!("== chokepoints");
+    // клетка, без которой часть карты перестает быть достижимой от спавнов
+    let ground = ground_graph(&graph, &info.level);
+    let reached_ground = reachable(&ground, &info.spawns, None);
+    let mut chokepoints = Vec::new();
+    for &pos in &reached_ground {
+        let without = reachable(&ground, &info.spawns, Some(pos));
+        let lost = reached_ground.len() - without.len() - 1;
+        if lost > 0 && !info.spawns.contains(&pos) {
+            chokepoints.push((pos, lost));
+        }
+    }
+    for (pos, lost) in &chokepoints {
+        println!("  ({},{}) cuts off {} tiles", pos.0, pos.1, lost);
+    }
+
+    println!("== dead ends");
+    // клетки с единственным соседом в обе стороны
+    let mut neighbours: BTreeMap<TilePos, BTreeSet<TilePos>> = BTreeMap::new();
+    for (&pos, next) in &ground {
+        for &next_pos in next {
+            neighbours.entry(pos).or_default().insert(next_pos);
+            neighbours.entry(next_pos).or_default().insert(pos);
+        }
+    }
+    let dead_ends: Vec<TilePos> = neighbours.iter()
+        .filter(|(pos, next)| next.len() == 1 && reached_ground.contains(pos))
+        .map(|(&pos, _)| pos)
+        .collect();
+    println!("  {}", format_tiles(&dead_ends));
+
+    println!("== loot distance from spawns, ticks");
+    let from_spawns: Vec<_> = info.spawns.iter()
+        .map(|&spawn| paths.build_paths_from(spawn, VerticalState::Default))
+        .collect();
+    for (pos, name) in &info.loot {
+        let distances: Vec<String> = from_spawns.iter()
+            .map(|map| {
+                map.iter()
+                    .filter(|((map_pos, _), _)| map_pos == pos)
+                    .map(|(_, &ticks)| ticks)
+                    .min()
+                    .map_or("-".to_owned(), |ticks| ticks.to_string())
+            })
+            .collect();
+        println!("  {:<14} ({},{}) {}", name, pos.0, pos.1, distances.join(" "));
+    }
+
+    println!("== map: P spawn, L loot, x unreachable, p pad only, c chokepoint, d dead end");
+    let mut marks = BTreeMap::new();
+    unreachable.iter().for_each(|&pos| { marks.insert(pos, 'x'); });
+    pad_only.iter().for_each(|&pos| { marks.insert(pos, 'p'); });
+    dead_ends.iter().for_each(|&pos| { marks.insert(pos, 'd'); });
+    chokepoints.iter().for_each(|&(pos, _)| { marks.insert(pos, 'c'); });
+    info.loot.iter().for_each(|&(pos, _)| { marks.insert(pos, 'L'); });
+    info.spawns.iter().for_each(|&pos| { marks.insert(pos, 'P'); });
+    print_map(&info, &marks);
+}
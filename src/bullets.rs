@@ -0,0 +1,224 @@
+use model::{Bullet, Game, Level, Mine, MineState, Properties, Vec2F64, WeaponType};
+
+use crate::collision::swept_aabb;
+use crate::explosion::{chain_explosions_marking, mine_center, Explosion};
+use crate::raycast::wall_hit;
+
+/// Куда и через сколько тиков долетит пуля, выпущенная из position в направлении aim
+pub fn bullet_end(position: Vec2F64, aim: Vec2F64, weapon_type: &WeaponType, level: &Level, properties: &Properties) -> (Vec2F64, f64) {
+    let weapon_params = &properties.weapon_params[weapon_type];
+    let aim_length = ((aim.x).powi(2) + (aim.y).powi(2)).sqrt();
+    if aim_length < 1e-9 {
+        return (position, 0.0);
+    }
+    // скорость за тик, чтобы время касания сразу было в тиках
+    let velocity = aim.mul(weapon_params.bullet.speed / aim_length / properties.ticks_per_second);
+    // граница мира - тоже стена, так что касание найдется всегда
+    wall_hit(position, velocity, weapon_params.bullet.size / 2.0, f64::INFINITY, level).unwrap_or((position, 0.0))
+}
+
+// попадания пуль: (положение пули, положение игрока, урон)
+pub type BulletHits = Vec<(Vec2F64, Vec2F64, i32)>;
+// попадания взрывов: (центр взрыва, положение игрока, урон, радиус)
+pub type ExplosionHits = Vec<(Vec2F64, Vec2F64, i32, f64)>;
+
+// пули и мины, которые могут нанести урон
+#[derive(Clone)]
+pub struct Bullets {
+    pub bullets: Vec<(Bullet, Vec2F64, f64)>, // Bullet, end pos, end tick (with microticks)
+    pub mines: Vec<Mine>,
+    pub mine_ticks: Vec<(Option<f64>, f64)>, // для мины: explosion tick (если уже сработала), tick, с которого может сработать
+}
+
+impl Bullets {
+    pub fn new(game: &Game) -> Bullets {
+        let mut bullets = Vec::new();
+        for bullet in &game.bullets {
+            let (end, tick) = bullet_end(bullet.position, bullet.velocity, &bullet.weapon_type, &game.level, &game.properties);
+            bullets.push((bullet.clone(), end, tick));
+        }
+        let mines: Vec<Mine> = game.mines.iter()
+            .filter(|mine| mine.state != MineState::Exploded)
+            .cloned()
+            .collect();
+        let mine_ticks = mines.iter()
+            .map(|mine| {
+                let timer_ticks = mine.timer.unwrap_or(0.0) * game.properties.ticks_per_second;
+                match mine.state {
+                    MineState::Triggered => (Some(timer_ticks), f64::INFINITY),
+                    MineState::Preparing => (None, timer_ticks),
+                    _ => (None, 0.0),
+                }
+            })
+            .collect();
+        Bullets {
+            bullets,
+            mines,
+            mine_ticks,
+        }
+    }
+
+    /// Оценивает попадания за интервал тиков [from_tick, to_tick], на котором игрок движется равномерно
+    /// из unit_positions.0 в unit_positions.1, и убирает пули, которые попали в игрока или в стены/границы.
+    /// Мины срабатывают, если игрок зашел в радиус срабатывания, и взрываются по таймеру, в том числе цепочкой.
+    pub fn test(&self, unit_positions: (Vec2F64, Vec2F64), unit_id: i32, from_tick: f64, to_tick: f64, bullets_state: &BulletsState, properties: &Properties)
+            -> (Option<BulletHits>, Option<ExplosionHits>, BulletsState) { // bullet hits, explosion hits, removed bullets
+        let (unit_position1, unit_position2) = unit_positions;
+        let duration = to_tick - from_tick;
+        let unit_half_size = properties.unit_size.mul(0.5);
+        let unit_velocity = if duration > 0.0 { unit_position2.sub(unit_position1).mul(1.0 / duration) } else { Vec2F64 { x: 0.0, y: 0.0 } };
+        let unit_center1 = unit_position1.add(Vec2F64 { x: 0.0, y: unit_half_size.y });
+        let unit_position_at = |tick: f64| unit_position1.add(unit_velocity.mul(tick - from_tick));
+
+        let mut bullet_hits = None;
+        let mut explosion_hits = None;
+
+        let mut new_bullet_state = bullets_state.clone();
+
+        for (index, bullet) in self.bullets.iter().enumerate() {
+            if bullets_state.is_bullet_removed(index) {
+                continue;
+            }
+
+            let bullet_velocity = bullet.0.velocity.mul(1.0 / properties.ticks_per_second);
+            let bullet_position = bullet.0.position.add(bullet_velocity.mul(from_tick));
+
+            // попадание пули, на свою пулю наткнуться нельзя
+            let half_bullet_size = bullet.0.size / 2.0;
+            let hit_time = if bullet.0.unit_id != unit_id {
+                swept_aabb(bullet_position, Vec2F64 { x: half_bullet_size, y: half_bullet_size }, bullet_velocity,
+                           unit_center1, unit_half_size, unit_velocity, bullet.2.min(to_tick) - from_tick)
+            } else {
+                None
+            };
+
+            let explosion_tick = if let Some(hit_time) = hit_time {
+                new_bullet_state.remove_bullet(index);
+                let unit_position = unit_position_at(from_tick + hit_time);
+                bullet_hits.get_or_insert_with(Vec::new).push((bullet_position.add(bullet_velocity.mul(hit_time)), unit_position, bullet.0.damage));
+                Some(from_tick + hit_time)
+            } else if bullet.2 <= to_tick {
+                // долетела до стены
+                new_bullet_state.remove_bullet(index);
+                Some(bullet.2)
+            } else {
+                None
+            };
+
+            // взрыв, вместе с минами, которые он подорвет
+            if let (Some(explosion_tick), Some(explosion_params)) = (explosion_tick, &bullet.0.explosion_params) {
+                let explosion_position = bullet.0.position.add(bullet_velocity.mul(explosion_tick));
+                let unit_position = unit_position_at(explosion_tick.max(from_tick));
+                let explosions = chain_explosions_marking(vec![Explosion::new(explosion_position, explosion_params)],
+                                                          &self.mines, new_bullet_state.exploded_mines(self.mines.len()));
+                for explosion in explosions {
+                    if explosion.hits_unit(unit_position, properties.unit_size) {
+                        explosion_hits.get_or_insert_with(Vec::new).push((explosion.position, unit_position, explosion.damage, explosion.radius));
+                    }
+                }
+            }
+        };
+
+        // мины
+        for (index, (mine, (explosion_tick, ready_tick))) in self.mines.iter().zip(&self.mine_ticks).enumerate() {
+            if new_bullet_state.is_mine_exploded(index) {
+                continue;
+            }
+            let mut explosion_tick = new_bullet_state.mine_explosion_tick(index).or(*explosion_tick);
+            if explosion_tick.is_none() {
+                // игрок заходит в радиус срабатывания готовой мины
+                let start_tick = from_tick.max(*ready_tick);
+                if start_tick <= to_tick {
+                    let trigger_half_size = Vec2F64 { x: mine.trigger_radius, y: mine.trigger_radius };
+                    let unit_center = unit_position_at(start_tick).add(Vec2F64 { x: 0.0, y: unit_half_size.y });
+                    if let Some(trigger_time) = swept_aabb(unit_center, unit_half_size, unit_velocity,
+                                                           mine_center(mine.position, properties), trigger_half_size, Vec2F64 { x: 0.0, y: 0.0 },
+                                                           to_tick - start_tick) {
+                        let tick = start_tick + trigger_time + properties.mine_trigger_time * properties.ticks_per_second;
+                        new_bullet_state.trigger_mine(index, tick);
+                        explosion_tick = Some(tick);
+                    }
+                }
+            }
+            if let Some(explosion_tick) = explosion_tick {
+                if explosion_tick <= to_tick {
+                    let unit_position = unit_position_at(explosion_tick.max(from_tick));
+                    let exploded = new_bullet_state.exploded_mines(self.mines.len());
+                    exploded[index] = true;
+                    let explosions = chain_explosions_marking(vec![Explosion::of_mine(mine.position, &mine.explosion_params, properties)],
+                                                              &self.mines, exploded);
+                    for explosion in explosions {
+                        if explosion.hits_unit(unit_position, properties.unit_size) {
+                            explosion_hits.get_or_insert_with(Vec::new).push((explosion.position, unit_position, explosion.damage, explosion.radius));
+                        }
+                    }
+                }
+            }
+        }
+
+        (bullet_hits, explosion_hits, new_bullet_state)
+    }
+
+    pub fn need_test(&self, bullets_state: &BulletsState) -> bool {
+        self.bullets.len() > bullets_state.count_removed_bullets() ||
+            self.mines.len() > bullets_state.count_exploded_mines()
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct BulletsState {
+    removed_bullets: Vec<bool>, // индекс пули в Bullets
+    exploded_mines: Vec<bool>, // индекс мины в Bullets
+    mine_explosion_ticks: Vec<Option<i32>>, // когда взорвется мина, которую задели (в микротиках, 1/100 тика)
+}
+
+impl BulletsState {
+    pub fn new() -> Self {
+        Self {
+            removed_bullets: vec![],
+            exploded_mines: vec![],
+            mine_explosion_ticks: vec![],
+        }
+    }
+
+    fn is_bullet_removed(&self, index: usize) -> bool {
+        *self.removed_bullets.get(index).unwrap_or(&false)
+    }
+
+    fn remove_bullet(&mut self, index: usize) {
+        while self.removed_bullets.len() <= index {
+            self.removed_bullets.push(false);
+        }
+        self.removed_bullets[index] = true;
+    }
+
+    fn count_removed_bullets(&self) -> usize {
+        self.removed_bullets.iter().filter(|b| **b).count()
+    }
+
+    fn is_mine_exploded(&self, index: usize) -> bool {
+        *self.exploded_mines.get(index).unwrap_or(&false)
+    }
+
+    fn exploded_mines(&mut self, mine_count: usize) -> &mut Vec<bool> {
+        while self.exploded_mines.len() < mine_count {
+            self.exploded_mines.push(false);
+        }
+        &mut self.exploded_mines
+    }
+
+    fn count_exploded_mines(&self) -> usize {
+        self.exploded_mines.iter().filter(|b| **b).count()
+    }
+
+    fn mine_explosion_tick(&self, index: usize) -> Option<f64> {
+        self.mine_explosion_ticks.get(index).cloned().unwrap_or(None).map(|micro_ticks| micro_ticks as f64 / 100.0)
+    }
+
+    fn trigger_mine(&mut self, index: usize, tick: f64) {
+        while self.mine_explosion_ticks.len() <= index {
+            self.mine_explosion_ticks.push(None);
+        }
+        self.mine_explosion_ticks[index] = Some((tick * 100.0).ceil() as i32);
+    }
+}
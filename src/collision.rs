@@ -0,0 +1,32 @@
+use model::Vec2F64;
+
+/// Первое время касания двух прямоугольников, движущихся равномерно, на интервале [0, max_time].
+/// Прямоугольники заданы центром и половинами размеров, скорости - смещение за единицу времени.
+/// Касание границами тоже считается.
+pub fn swept_aabb(center1: Vec2F64, half1: Vec2F64, velocity1: Vec2F64,
+                  center2: Vec2F64, half2: Vec2F64, velocity2: Vec2F64, max_time: f64) -> Option<f64> {
+    if max_time < 0.0 {
+        return None;
+    }
+    // движение первого прямоугольника относительно второго, который расширен на размер первого
+    let delta = center2.sub(center1);
+    let velocity = velocity1.sub(velocity2);
+    let mut t_enter = 0.0_f64;
+    let mut t_exit = max_time;
+    for &(d, v, half) in &[(delta.x, velocity.x, half1.x + half2.x), (delta.y, velocity.y, half1.y + half2.y)] {
+        if v == 0.0 {
+            if d.abs() > half {
+                return None;
+            }
+            continue;
+        }
+        let t1 = (d - half) / v;
+        let t2 = (d + half) / v;
+        t_enter = t_enter.max(t1.min(t2));
+        t_exit = t_exit.min(t1.max(t2));
+        if t_enter > t_exit {
+            return None;
+        }
+    }
+    Some(t_enter)
+}
@@ -0,0 +1,161 @@
+use model::{ColorF32, Properties, Vec2F32, Vec2F64};
+
+use crate::bullets::{Bullets, BulletsState};
+use crate::fnv_hash::FnvHashMap;
+use crate::path::{Move, Paths, TilePos, VerticalState};
+
+/// Карта опасности: урон игроку, который стоит в вершине графа весь интервал тиков,
+/// от летящих пуль, взрывов ракет и мин. Считается раз за тик, дальше пути оцениваются поиском по таблице.
+/// Интервалы считаются подряд: пуля или мина, которая взорвалась в одном интервале, в следующих уже не учитывается.
+pub struct DangerMap {
+    bucket_ticks: i32,
+    damage: FnvHashMap<(TilePos, VerticalState), Vec<i32>>,
+}
+
+impl DangerMap {
+    /// урон по интервалам bucket_ticks на ticks тиков вперед для всех вершин графа
+    pub fn new(paths: &Paths, unit_id: i32, bullets: &Bullets, ticks: i32, bucket_ticks: i32, properties: &Properties) -> Self {
+        let mut damage = FnvHashMap::default();
+        if !bullets.need_test(&BulletsState::new()) {
+            return DangerMap { bucket_ticks, damage };
+        }
+        // положение игрока зависит только от клетки, так что считаем по клеткам
+        let mut tile_damage: FnvHashMap<TilePos, Vec<i32>> = FnvHashMap::default();
+        for &(pos, vertical_state) in paths.outgoing.keys() {
+            let buckets = tile_damage.entry(pos)
+                .or_insert_with(|| DangerMap::tile_damage(pos, unit_id, bullets, ticks, bucket_ticks, properties));
+            if buckets.iter().any(|damage| *damage > 0) {
+                damage.insert((pos, vertical_state), buckets.clone());
+            }
+        }
+        DangerMap { bucket_ticks, damage }
+    }
+
+    fn tile_damage(pos: TilePos, unit_id: i32, bullets: &Bullets, ticks: i32, bucket_ticks: i32, properties: &Properties) -> Vec<i32> {
+        let unit_position = Vec2F64 { x: pos.0 as f64 + 0.5, y: pos.1 as f64 };
+        let mut bullets_state = BulletsState::new();
+        (0..(ticks + bucket_ticks - 1) / bucket_ticks)
+            .map(|bucket| {
+                let from_tick = (bucket * bucket_ticks) as f64;
+                let (bullet_hits, explosion_hits, new_bullets_state) = bullets.test((unit_position, unit_position), unit_id,
+                                                                                    from_tick, from_tick + bucket_ticks as f64, &bullets_state, properties);
+                bullets_state = new_bullets_state;
+                bullet_hits.unwrap_or_default().iter().map(|hit| hit.2).sum::<i32>() +
+                    explosion_hits.unwrap_or_default().iter().map(|hit| hit.2).sum::<i32>()
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.damage.is_empty()
+    }
+
+    /// урон за интервал, в который попадает tick
+    pub fn damage_at(&self, pos: TilePos, vertical_state: VerticalState, tick: i32) -> i32 {
+        self.damage.get(&(pos, vertical_state))
+            .and_then(|buckets| buckets.get((tick / self.bucket_ticks) as usize))
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    /// Оценка урона на пути (path[0] - стартовая вершина): первую половину хода игрок в начальной вершине, вторую - в конечной.
+    /// В каждом интервале берется самая опасная из пройденных вершин.
+    pub fn path_damage(&self, path: &[Move]) -> i32 {
+        if self.is_empty() {
+            return 0;
+        }
+        let mut bucket_damage: Vec<i32> = Vec::new();
+        let mut ticks = 0;
+        for mov in &path[1..] {
+            for mov_tick in 0..mov.ticks {
+                let (pos, vertical_state) = if mov_tick * 2 < mov.ticks {
+                    (mov.pos1, mov.vertical_state1)
+                } else {
+                    (mov.pos2, mov.vertical_state2)
+                };
+                let tick = ticks + mov_tick;
+                let bucket = (tick / self.bucket_ticks) as usize;
+                if bucket_damage.len() <= bucket {
+                    bucket_damage.resize(bucket + 1, 0);
+                }
+                bucket_damage[bucket] = bucket_damage[bucket].max(self.damage_at(pos, vertical_state, tick));
+            }
+            ticks += mov.ticks;
+        }
+        bucket_damage.iter().sum()
+    }
+
+    /// клетки с уроном: чем краснее, тем больше урон за все время
+    pub fn draw(&self, properties: &Properties, debug: &mut crate::Debug) {
+        let mut tiles: FnvHashMap<TilePos, i32> = FnvHashMap::default();
+        for ((pos, _), buckets) in &self.damage {
+            let total = buckets.iter().sum();
+            let entry = tiles.entry(*pos).or_insert(0);
+            *entry = (*entry).max(total);
+        }
+        for (pos, damage) in tiles {
+            let alpha = (damage as f32 / properties.unit_max_health as f32).min(1.0) * 0.5;
+            debug.draw(model::CustomData::Rect {
+                pos: Vec2F32 { x: pos.0 as f32, y: pos.1 as f32 },
+                size: Vec2F32 { x: 1.0, y: 1.0 },
+                color: ColorF32 {
+                    r: 1.0,
+                    g: 0.0,
+                    b: 0.0,
+                    a: alpha,
+                },
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use model::{Bullet, Mine, MineState, Vec2F64, WeaponType};
+
+    use crate::bullets::Bullets;
+    use crate::test_utils::{game, level, properties, unit};
+
+    use super::DangerMap;
+
+    #[test]
+    fn explosions_counted_once_across_buckets() {
+        let properties = properties();
+        let rocket = &properties.weapon_params[&WeaponType::RocketLauncher];
+        let mut game = game(level(&[
+            "############",
+            "#..........#",
+            "#..........#",
+            "###........#",
+            "############",
+        ]), vec![unit(1, 1, 5.5, 1.0, None, &properties)]);
+        // своя ракета летит влево в стену рядом с игроком, мина справа уже сработала
+        game.bullets.push(Bullet {
+            weapon_type: WeaponType::RocketLauncher,
+            unit_id: 1,
+            player_id: 1,
+            position: Vec2F64 { x: 4.5, y: 1.5 },
+            velocity: Vec2F64 { x: -rocket.bullet.speed, y: 0.0 },
+            damage: rocket.bullet.damage,
+            size: rocket.bullet.size,
+            explosion_params: rocket.explosion.clone(),
+        });
+        game.mines.push(Mine {
+            player_id: 2,
+            position: Vec2F64 { x: 8.5, y: 1.0 },
+            size: properties.mine_size,
+            state: MineState::Triggered,
+            timer: Some(0.75),
+            trigger_radius: properties.mine_trigger_radius,
+            explosion_params: properties.mine_explosion_params.clone(),
+        });
+        let bullets = Bullets::new(&game);
+
+        let buckets = DangerMap::tile_damage((5, 1), 1, &bullets, 60, 10, &properties);
+
+        let explosion_damage = properties.mine_explosion_params.damage;
+        assert_eq!(rocket.explosion.as_ref().unwrap().damage, explosion_damage);
+        // ракета взрывается в первом интервале, мина - через 45 тиков
+        assert_eq!(buckets, vec![explosion_damage, 0, 0, 0, explosion_damage, 0]);
+    }
+}
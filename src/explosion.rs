@@ -0,0 +1,81 @@
+use model::{ExplosionParams, Mine, MineState, Properties, Vec2F64};
+
+use crate::fnv_hash::FnvHashMap;
+
+/// Взрыв по правилам сервера: квадрат со стороной 2 * radius вокруг центра задевает всех игроков,
+/// прямоугольник которых с ним пересекается, в том числе своих и самого взорвавшего.
+#[derive(Clone, Debug)]
+pub struct Explosion {
+    pub position: Vec2F64,
+    pub radius: f64,
+    pub damage: i32,
+}
+
+impl Explosion {
+    pub fn new(position: Vec2F64, params: &ExplosionParams) -> Self {
+        Self {
+            position,
+            radius: params.radius,
+            damage: params.damage,
+        }
+    }
+
+    /// взрыв мины, стоящей в mine_position (середина низа)
+    pub fn of_mine(mine_position: Vec2F64, params: &ExplosionParams, properties: &Properties) -> Self {
+        Explosion::new(mine_center(mine_position, properties), params)
+    }
+
+    /// задевает ли взрыв игрока, стоящего в unit_position
+    pub fn hits_unit(&self, unit_position: Vec2F64, unit_size: Vec2F64) -> bool {
+        (unit_position.x - self.position.x).abs() <= unit_size.x / 2.0 + self.radius &&
+            (unit_position.y + unit_size.y / 2.0 - self.position.y).abs() <= unit_size.y / 2.0 + self.radius
+    }
+
+    /// задевает ли взрыв мину (мина от этого взрывается)
+    fn hits_mine(&self, mine: &Mine) -> bool {
+        (mine.position.x - self.position.x).abs() <= mine.size.x / 2.0 + self.radius &&
+            (mine.position.y + mine.size.y / 2.0 - self.position.y).abs() <= mine.size.y / 2.0 + self.radius
+    }
+}
+
+pub fn mine_center(mine_position: Vec2F64, properties: &Properties) -> Vec2F64 {
+    mine_position.add(Vec2F64 { x: 0.0, y: properties.mine_size.y / 2.0 })
+}
+
+/// Цепная реакция: исходные взрывы и все мины, которые взорвутся от них (и друг от друга).
+/// Уже взорвавшиеся мины не учитываются.
+pub fn chain_explosions(explosions: Vec<Explosion>, mines: &[Mine]) -> Vec<Explosion> {
+    chain_explosions_marking(explosions, mines, &mut vec![false; mines.len()])
+}
+
+/// то же, что chain_explosions, но пропускает и отмечает мины, взорванные в exploded (индекс в mines)
+pub fn chain_explosions_marking(explosions: Vec<Explosion>, mines: &[Mine], exploded: &mut [bool]) -> Vec<Explosion> {
+    let mut result = explosions;
+    let mut index = 0;
+    while index < result.len() {
+        for (mine_index, mine) in mines.iter().enumerate() {
+            if exploded[mine_index] || mine.state == MineState::Exploded {
+                continue;
+            }
+            if result[index].hits_mine(mine) {
+                exploded[mine_index] = true;
+                result.push(Explosion::new(mine.position.add(Vec2F64 { x: 0.0, y: mine.size.y / 2.0 }), &mine.explosion_params));
+            }
+        }
+        index += 1;
+    }
+    result
+}
+
+/// суммарный урон от взрывов по игрокам (id, положение, размер)
+pub fn explosions_damage(explosions: &[Explosion], units: &[(i32, Vec2F64, Vec2F64)]) -> FnvHashMap<i32, i32> {
+    let mut damage = FnvHashMap::default();
+    for explosion in explosions {
+        for (id, position, size) in units {
+            if explosion.hits_unit(*position, *size) {
+                *damage.entry(*id).or_insert(0) += explosion.damage;
+            }
+        }
+    }
+    damage
+}
@@ -0,0 +1,177 @@
+use model::{Game, Item, LootBox, Unit, WeaponType};
+
+use crate::weapon::{is_weapon_upgrade, weapon_value};
+
+// за сколько тиков ценность цели падает вдвое
+const GOAL_HORIZON: f64 = 60.0;
+// без оружия ничего сделать нельзя
+const WEAPON_VALUE: f64 = 1000.0;
+// ценность прибавки в DPS при смене оружия на лучшее
+const WEAPON_UPGRADE_VALUE: f64 = 2.0;
+// ценность единицы восстановленного здоровья, растет по мере потери здоровья
+const HEALTH_VALUE: f64 = 1.0;
+// ценность единицы урона от мин, если на убийство их пока не хватает
+const MINE_DAMAGE_VALUE: f64 = 0.5;
+// лишние мины (на убийство и так хватает) - для расстановки
+const SPARE_MINE_VALUE: f64 = 0.2;
+// ценность единицы здоровья, которую не получит противник, если аптечку заберем мы
+const DENY_VALUE: f64 = 0.5;
+// на сколько тиков противник должен опередить нас, чтобы считать предмет проигранным
+const RACE_MARGIN: i32 = 3;
+// ценность боя с противником, расстояние до него учитывает оценка пути
+const ENEMY_VALUE: f64 = 40.0;
+
+/// Цель движения: какой предмет собирать, или идти к противнику
+#[derive(Clone, Debug, PartialEq)]
+pub enum Goal {
+    // любое оружие, если своего нет, или конкретное - лучше нашего
+    Weapon(Option<WeaponType>),
+    Health,
+    Mine,
+    Enemy,
+}
+
+impl Goal {
+    /// подходит ли предмет под цель (для целей-предметов)
+    pub fn is_goal_loot(&self, loot: &LootBox) -> bool {
+        match self {
+            Goal::Weapon(None) => is_weapon(loot),
+            Goal::Weapon(Some(weapon_type)) => match &loot.item {
+                Item::Weapon { weapon_type: loot_weapon_type } => loot_weapon_type == weapon_type,
+                _ => false,
+            },
+            Goal::Health => is_health(loot),
+            Goal::Mine => is_mine(loot),
+            Goal::Enemy => false,
+        }
+    }
+}
+
+/// Предмет и время, за которое до него дойдем мы и каждый из противников (id, тики)
+pub struct LootTicks<'a> {
+    pub loot: &'a LootBox,
+    pub ticks: i32,
+    pub enemy_ticks: Vec<(i32, i32)>,
+}
+
+impl LootTicks<'_> {
+    /// противники, которым предмет нужен (они его подберут, если дойдут), и их время
+    fn wanting_enemies<'b>(&'b self, game: &'b Game) -> impl Iterator<Item=(&'b Unit, i32)> + 'b {
+        self.enemy_ticks.iter()
+            .filter_map(move |(id, ticks)| game.units.iter().find(|unit2| unit2.id == *id).map(|enemy| (enemy, *ticks)))
+            .filter(move |(enemy, _)| enemy_wants(enemy, self.loot, game))
+    }
+
+    /// кто-то из противников, кому предмет нужен, дойдет до него заметно раньше нас
+    pub fn is_lost(&self, game: &Game) -> bool {
+        self.wanting_enemies(game).any(|(_, enemy_ticks)| enemy_ticks + RACE_MARGIN <= self.ticks)
+    }
+}
+
+/// подберет ли противник предмет: аптечку - если ранен, оружие - если нет своего, мины - всегда
+fn enemy_wants(enemy: &Unit, loot: &LootBox, game: &Game) -> bool {
+    match &loot.item {
+        Item::HealthPack { .. } => enemy.health < game.properties.unit_max_health,
+        Item::Weapon { .. } => enemy.weapon.is_none(),
+        Item::Mine {} => true,
+    }
+}
+
+pub fn is_weapon(loot: &LootBox) -> bool { matches!(loot.item, Item::Weapon { .. }) }
+
+pub fn is_health(loot: &LootBox) -> bool { matches!(loot.item, Item::HealthPack { .. }) }
+
+pub fn is_mine(loot: &LootBox) -> bool { matches!(loot.item, Item::Mine { .. }) }
+
+/// урон от подрыва себя: все наши мины и взрыв ракеты
+pub fn suicide_damage(unit: &Unit, game: &Game) -> i32 {
+    unit.mines * game.properties.mine_explosion_params.damage +
+        match &unit.weapon {
+            Some(weapon) if weapon.typ == WeaponType::RocketLauncher => weapon.params.explosion.as_ref().map(|explosion| explosion.damage).unwrap_or(0),
+            _ => 0,
+        }
+}
+
+/// Выбор цели по ценности с учетом расстояния: ценность / (1 + ticks / GOAL_HORIZON).
+/// loot_ticks - предметы, до которых мы можем дойти, проигранные противнику уже исключены.
+/// Возвращает цель и оценки всех вариантов (для отладки).
+pub fn choose_goal(unit: &Unit, game: &Game, loot_ticks: &[LootTicks]) -> (Goal, Vec<(Goal, f64)>) {
+    let properties = &game.properties;
+    let nearest = |goal: &Goal| loot_ticks.iter()
+        .filter(|entry| goal.is_goal_loot(entry.loot))
+        .map(|entry| entry.ticks)
+        .min();
+    let discount = |value: f64, ticks: i32| value / (1.0 + ticks as f64 / GOAL_HORIZON);
+
+    let mut scores = vec![(Goal::Enemy, ENEMY_VALUE)];
+
+    match &unit.weapon {
+        None => if let Some(ticks) = nearest(&Goal::Weapon(None)) {
+            scores.push((Goal::Weapon(None), discount(WEAPON_VALUE, ticks)));
+        },
+        Some(weapon) => {
+            // крюк за оружием лучше нашего
+            let current_value = weapon_value(&weapon.typ, unit, game);
+            for weapon_type in &[WeaponType::Pistol, WeaponType::AssaultRifle, WeaponType::RocketLauncher] {
+                if !is_weapon_upgrade(weapon_type, unit, game) {
+                    continue;
+                }
+                let goal = Goal::Weapon(Some(weapon_type.clone()));
+                if let Some(ticks) = nearest(&goal) {
+                    let gain = weapon_value(weapon_type, unit, game) - current_value;
+                    scores.push((goal, discount(gain * WEAPON_UPGRADE_VALUE, ticks)));
+                }
+            }
+        }
+    }
+
+    // аптечку подбирает только раненый, поэтому и отнять ее у противника можем, только если ранены сами
+    let missing_health = properties.unit_max_health - unit.health;
+    if missing_health > 0 {
+        let restored = missing_health.min(properties.health_pack_health) as f64;
+        let urgency = 1.0 + missing_health as f64 / properties.unit_max_health as f64;
+        let best = loot_ticks.iter()
+            .filter(|entry| is_health(entry.loot))
+            .map(|entry| {
+                // самый раненый из противников, кто идет к этой аптечке позже нас
+                let denied = entry.wanting_enemies(game)
+                    .filter(|(_, enemy_ticks)| *enemy_ticks >= entry.ticks)
+                    .map(|(enemy, _)| (properties.unit_max_health - enemy.health).min(properties.health_pack_health))
+                    .max()
+                    .unwrap_or(0) as f64;
+                discount(restored * urgency * HEALTH_VALUE + denied * DENY_VALUE, entry.ticks)
+            })
+            .fold(None, |best: Option<f64>, score| Some(best.unwrap_or(score).max(score)));
+        if let Some(score) = best {
+            scores.push((Goal::Health, score));
+        }
+    }
+
+    // мины нужны, только чтобы подорвать противника вместе с собой, а для этого нужно оружие
+    if unit.weapon.is_some() {
+        if let Some(ticks) = nearest(&Goal::Mine) {
+            let weakest_enemy = game.units.iter()
+                .filter(|unit2| unit2.player_id != unit.player_id)
+                .map(|unit2| unit2.health)
+                .min()
+                .unwrap_or(properties.unit_max_health);
+            let damage = suicide_damage(unit, game);
+            let mine_damage = properties.mine_explosion_params.damage;
+            let value = if damage >= weakest_enemy {
+                mine_damage as f64 * SPARE_MINE_VALUE
+            } else if damage + mine_damage >= weakest_enemy {
+                // с этой миной подрыв убивает
+                weakest_enemy as f64
+            } else {
+                mine_damage as f64 * MINE_DAMAGE_VALUE
+            };
+            scores.push((Goal::Mine, discount(value, ticks)));
+        }
+    }
+
+    let goal = scores.iter()
+        .fold((&Goal::Enemy, f64::MIN), |best, (goal, score)| if *score > best.1 { (goal, *score) } else { best })
+        .0
+        .clone();
+    (goal, scores)
+}
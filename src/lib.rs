@@ -0,0 +1,32 @@
+pub mod my_strategy;
+mod aim;
+mod collision;
+mod bullets;
+mod danger;
+mod explosion;
+mod goal;
+mod mcts;
+pub mod path;
+pub mod properties;
+mod fnv_hash;
+mod non_nan_f64;
+mod rand;
+mod raycast;
+mod unit_sim;
+mod visibility;
+mod weapon;
+mod mine_tactics;
+mod recovery;
+#[cfg(test)]
+mod test_utils;
+
+pub struct Debug<'a>(pub &'a mut dyn std::io::Write);
+
+impl Debug<'_> {
+    fn draw(&mut self, data: model::CustomData) {
+        use trans::Trans;
+        model::PlayerMessageGame::CustomDataMessage { data }
+            .write_to(&mut self.0)
+            .expect("Failed to write custom debug data");
+    }
+}
@@ -1,10 +1,5 @@
-mod my_strategy;
-mod path;
-mod fnv_hash;
-mod non_nan_f64;
-mod rand;
-
-use my_strategy::MyStrategy;
+use aicup2019::my_strategy::{self, MyStrategy};
+use aicup2019::Debug;
 
 struct Args {
     host: String,
@@ -25,20 +20,34 @@ impl Args {
     }
 }
 
-struct Runner {
-    reader: Box<dyn std::io::BufRead>,
-    writer: Box<dyn std::io::Write>,
+// файл для записи сообщений сервера (для офлайн анализа карты и повторов)
+const CAPTURE_ENV: &str = "AICUP_CAPTURE";
+// зерно случайных чисел стратегии
+const SEED_ENV: &str = "AICUP_SEED";
+// проверка воспроизводимости: прогнать стратегию по записи игры дважды и сравнить действия
+const REPLAY_CHECK_ENV: &str = "AICUP_REPLAY_CHECK";
+
+fn seed() -> u64 {
+    std::env::var(SEED_ENV)
+        .map(|seed| seed.parse().expect("Can't parse seed"))
+        .unwrap_or(my_strategy::DEFAULT_SEED)
 }
 
-pub struct Debug<'a>(&'a mut dyn std::io::Write);
+/// действия своих игроков, в порядке их следования в game.units
+fn get_actions(strategy: &mut MyStrategy, player_view: &model::PlayerView, debug: &mut Debug) -> Vec<(i32, model::UnitAction)> {
+    player_view
+        .game
+        .units
+        .iter()
+        .filter(|unit| unit.player_id == player_view.my_id)
+        .map(|unit| (unit.id, strategy.get_action(unit, &player_view.game, debug)))
+        .collect()
+}
 
-impl Debug<'_> {
-    fn draw(&mut self, data: model::CustomData) {
-        use trans::Trans;
-        model::PlayerMessageGame::CustomDataMessage { data }
-            .write_to(&mut self.0)
-            .expect("Failed to write custom debug data");
-    }
+struct Runner {
+    reader: Box<dyn std::io::BufRead>,
+    writer: Box<dyn std::io::Write>,
+    capture: Option<Box<dyn std::io::Write>>,
 }
 
 impl Runner {
@@ -52,31 +61,32 @@ impl Runner {
         let mut writer = std::io::BufWriter::new(stream_clone);
         args.token.write_to(&mut writer)?;
         writer.flush()?;
+        let capture: Option<Box<dyn std::io::Write>> = match std::env::var(CAPTURE_ENV) {
+            Ok(path) => Some(Box::new(std::io::BufWriter::new(std::fs::File::create(path)?))),
+            Err(_) => None,
+        };
         Ok(Self {
             reader: Box::new(reader),
             writer: Box::new(writer),
+            capture,
         })
     }
     fn run(mut self) -> std::io::Result<()> {
         use trans::Trans;
-        let mut strategy = MyStrategy::new();
+        let mut strategy = MyStrategy::new(seed(), true);
         loop {
             let message = model::ServerMessageGame::read_from(&mut self.reader)?;
+            if let Some(capture) = &mut self.capture {
+                message.write_to(&mut *capture)?;
+                capture.flush()?;
+            }
             let player_view = match message.player_view {
                 Some(view) => view,
                 None => break,
             };
-            let mut actions = std::collections::HashMap::new();
-            for unit in player_view
-                .game
-                .units
-                .iter()
-                .filter(|unit| unit.player_id == player_view.my_id)
-            {
-                let action =
-                    strategy.get_action(unit, &player_view.game, &mut Debug(&mut self.writer));
-                actions.insert(unit.id, action);
-            }
+            let actions = get_actions(&mut strategy, &player_view, &mut Debug(&mut self.writer))
+                .into_iter()
+                .collect();
             let message = model::PlayerMessageGame::ActionMessage {
                 action: model::Versioned { inner: actions },
             };
@@ -87,6 +97,45 @@ impl Runner {
     }
 }
 
+/// Все действия стратегии на записи игры: (тик, id игрока, действие)
+fn replay_actions(capture: &[u8], seed: u64) -> std::io::Result<Vec<(i32, i32, String)>> {
+    use trans::Trans;
+    let mut reader = capture;
+    // без ограничений по времени, иначе решения зависят от скорости машины
+    let mut strategy = MyStrategy::new(seed, false);
+    let mut debug_sink = std::io::sink();
+    let mut result = Vec::new();
+    while !reader.is_empty() {
+        let message = model::ServerMessageGame::read_from(&mut reader)?;
+        let player_view = match message.player_view {
+            Some(view) => view,
+            None => break,
+        };
+        for (id, action) in get_actions(&mut strategy, &player_view, &mut Debug(&mut debug_sink)) {
+            result.push((player_view.game.current_tick, id, format!("{:?}", action)));
+        }
+    }
+    Ok(result)
+}
+
+fn check_replay(path: &str) -> std::io::Result<()> {
+    let capture = std::fs::read(path)?;
+    let seed = seed();
+    let first = replay_actions(&capture, seed)?;
+    let second = replay_actions(&capture, seed)?;
+    if let Some((action1, action2)) = first.iter().zip(&second).find(|(action1, action2)| action1 != action2) {
+        return Err(std::io::Error::other(format!("replay diverged at tick {} unit {}:\n{}\n{}", action1.0, action1.1, action1.2, action2.2)));
+    }
+    if first.len() != second.len() {
+        return Err(std::io::Error::other(format!("replay lengths differ: {} and {}", first.len(), second.len())));
+    }
+    eprintln!("replay check ok: {} actions, seed {}", first.len(), seed);
+    Ok(())
+}
+
 fn main() -> std::io::Result<()> {
+    if let Ok(path) = std::env::var(REPLAY_CHECK_ENV) {
+        return check_replay(&path);
+    }
     Runner::new(&Args::parse())?.run()
 }
@@ -0,0 +1,474 @@
+use std::fmt::{Debug, Error, Formatter};
+use std::time::Instant;
+
+use model::Weapon;
+
+use crate::path::{make_start_node, Move, Paths, TilePos, VerticalState};
+use crate::rand::Random;
+
+// коэффициент исследования UCB1, в единицах стоимости пути
+const EXPLORATION: f64 = 30.0;
+// стоимость пути ограничиваем, чтобы недостижимая цель не забивала средние значения
+const MAX_COST: f64 = 10000.0;
+// при переиспользовании поддерева прошлого тика его статистика учитывается с этим весом
+const REUSE_DECAY: f64 = 0.5;
+// дерево больше этого строится заново, а не переиспользуется
+const MAX_NODES: usize = 100000;
+
+/// Модель оружия для дерева: стрельба раз в fire_ticks тиков, после магазина - перезарядка
+#[derive(Clone, Debug)]
+pub struct WeaponModel {
+    pub fire_ticks: i32,
+    pub reload_ticks: i32,
+    pub magazine_size: i32,
+}
+
+impl WeaponModel {
+    pub fn new(weapon: &Weapon, ticks_per_second: f64) -> Self {
+        WeaponModel {
+            fire_ticks: (weapon.params.fire_rate * ticks_per_second).ceil() as i32,
+            reload_ticks: (weapon.params.reload_time * ticks_per_second).ceil() as i32,
+            magazine_size: weapon.params.magazine_size,
+        }
+    }
+}
+
+/// Состояние оружия в вершине: через сколько тиков от корня можно стрелять и сколько патронов в магазине
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FireState {
+    pub ready_tick: i32,
+    pub magazine: i32,
+}
+
+impl FireState {
+    fn can_fire(&self, tick: i32) -> bool {
+        self.ready_tick <= tick && self.magazine > 0
+    }
+
+    fn after_shot(&self, tick: i32, weapon: &WeaponModel) -> FireState {
+        if self.magazine > 1 {
+            FireState { ready_tick: tick + weapon.fire_ticks, magazine: self.magazine - 1 }
+        } else {
+            FireState { ready_tick: tick + weapon.reload_ticks, magazine: weapon.magazine_size }
+        }
+    }
+}
+
+struct Node {
+    pos: TilePos,
+    vertical_state: VerticalState,
+    // тик от начала поиска (у переиспользованного дерева корень не на нуле)
+    tick: i32,
+    fire_state: FireState,
+    // ребра: ход и стрелять ли в его начале; вершина создается при первом проходе по ребру
+    edges: Vec<(Move, bool, Option<usize>)>,
+    visits: f64,
+    value_sum: f64,
+}
+
+/// Дерево поиска: вершины - (клетка, состояние по вертикали, состояние оружия), ребра - ход по графу и стрельба/ожидание.
+/// Вершины хранятся в массиве, корень можно сдвинуть на потомка и продолжить поиск на следующем тике.
+pub struct Tree {
+    nodes: Vec<Node>,
+    root: usize,
+}
+
+impl Debug for Tree {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "Tree {{ nodes: {}, root: {}, visits: {} }}", self.nodes.len(), self.root, self.root_visits())
+    }
+}
+
+impl Tree {
+    pub fn new(pos: TilePos, vertical_state: VerticalState, fire_state: FireState) -> Self {
+        Tree {
+            nodes: vec![Node { pos, vertical_state, tick: 0, fire_state, edges: Vec::new(), visits: 0.0, value_sum: 0.0 }],
+            root: 0,
+        }
+    }
+
+    /// Переиспользовать поддерево: корнем становится самый посещаемый потомок корня в вершине (pos, vertical_state)
+    /// с тем же состоянием оружия, что в игре. None, если такого нет. Остальные вершины выбрасываются.
+    pub fn reroot(self, pos: TilePos, vertical_state: VerticalState, fire_state: FireState) -> Option<Tree> {
+        if self.nodes.len() > MAX_NODES {
+            return None;
+        }
+        // оружие, готовое к выстрелу, в дереве и в игре считается одинаково
+        let normalize = |state: FireState| FireState { ready_tick: state.ready_tick.max(0), magazine: state.magazine };
+        let nodes = &self.nodes;
+        let same_fire_state = |child: usize| {
+            let relative = FireState { ready_tick: nodes[child].fire_state.ready_tick - nodes[child].tick, magazine: nodes[child].fire_state.magazine };
+            normalize(relative) == normalize(fire_state)
+        };
+        let new_root = nodes[self.root].edges.iter()
+            .filter_map(|(_, _, child)| *child)
+            .filter(|&child| nodes[child].pos == pos && nodes[child].vertical_state == vertical_state && same_fire_state(child))
+            .max_by(|&a, &b| nodes[a].visits.partial_cmp(&nodes[b].visits).unwrap())?;
+        // переносим поддерево в новый массив в порядке обхода в ширину: вершина nodes[i] была old_nodes[order[i]]
+        let mut old_nodes: Vec<Option<Node>> = self.nodes.into_iter().map(Some).collect();
+        let mut nodes = Vec::new();
+        let mut order = vec![new_root];
+        while nodes.len() < order.len() {
+            let mut node = old_nodes[order[nodes.len()]].take().unwrap();
+            for (_, _, child) in &mut node.edges {
+                if let Some(old_child) = *child {
+                    *child = Some(order.len());
+                    order.push(old_child);
+                }
+            }
+            node.visits *= REUSE_DECAY;
+            node.value_sum *= REUSE_DECAY;
+            nodes.push(node);
+        }
+        Some(Tree { nodes, root: 0 })
+    }
+
+    pub fn root_visits(&self) -> f64 {
+        self.nodes[self.root].visits
+    }
+}
+
+/// Лучший план: ходы по самым посещаемым ребрам (первый элемент - стартовая вершина) и стрелять ли на первом ходе
+pub struct Plan {
+    pub path: Vec<Move>,
+    pub fire: bool,
+    pub value: f64,
+}
+
+/// Параметры поиска на один тик
+pub struct Planner<'a> {
+    pub paths: &'a Paths,
+    pub weapon: Option<WeaponModel>,
+    // глубина дерева и прогонов в тиках
+    pub horizon: i32,
+    pub iterations: usize,
+    // None - без ограничения по времени, только по итерациям
+    pub time_limit_ms: Option<u128>,
+}
+
+impl Planner<'_> {
+    /// Поиск: выбор ребра по UCB1, раскрытие одного нового ребра, прогон до горизонта и обновление статистики пути.
+    /// Ценность - ожидаемые очки за выстрелы (урон и убийства) минус стоимость всего пути: урон нам (наше здоровье
+    /// и очки противника) и удаленность от цели.
+    /// sample - выбор следующего хода прогона, path_cost - стоимость пути (None при столкновении),
+    /// shot_value - ожидаемые очки за выстрел с клетки на тике.
+    pub fn search(&self, tree: &mut Tree, rand: &mut Random,
+                  sample: &dyn Fn(&[Move], &mut Random) -> usize,
+                  path_cost: &mut dyn FnMut(&[Move]) -> Option<f64>,
+                  shot_value: &mut dyn FnMut(TilePos, i32) -> f64) -> usize {
+        let start = Instant::now();
+        let root_tick = tree.nodes[tree.root].tick;
+        let mut iterations = 0;
+        while iterations < self.iterations && self.time_limit_ms.is_none_or(|limit| start.elapsed().as_millis() < limit) {
+            iterations += 1;
+            let root = &tree.nodes[tree.root];
+            let mut path = vec![make_start_node(root.pos, root.vertical_state)];
+            let mut visited = vec![tree.root];
+            let mut shots = 0.0;
+
+            // выбор и раскрытие
+            let mut current = tree.root;
+            loop {
+                if tree.nodes[current].tick - root_tick >= self.horizon {
+                    break;
+                }
+                self.expand_edges(tree, current);
+                if tree.nodes[current].edges.is_empty() {
+                    break;
+                }
+                let edge = self.select_edge(tree, current, rand);
+                let (mov, fire, child) = tree.nodes[current].edges[edge].clone();
+                let node = &tree.nodes[current];
+                if fire {
+                    shots += shot_value(node.pos, node.tick - root_tick);
+                }
+                path.push(mov.clone());
+                let (child, created) = match child {
+                    Some(child) => (child, false),
+                    None => {
+                        let fire_state = match (&self.weapon, fire) {
+                            (Some(weapon), true) => node.fire_state.after_shot(node.tick, weapon),
+                            _ => node.fire_state,
+                        };
+                        let child = Node {
+                            pos: mov.pos2,
+                            vertical_state: mov.vertical_state2,
+                            tick: node.tick + mov.ticks,
+                            fire_state,
+                            edges: Vec::new(),
+                            visits: 0.0,
+                            value_sum: 0.0,
+                        };
+                        tree.nodes.push(child);
+                        let index = tree.nodes.len() - 1;
+                        tree.nodes[current].edges[edge].2 = Some(index);
+                        (index, true)
+                    }
+                };
+                visited.push(child);
+                current = child;
+                if created {
+                    break;
+                }
+            }
+
+            // прогон: ходы по политике, стреляем при первой возможности
+            let node = &tree.nodes[current];
+            let mut tick = node.tick - root_tick;
+            let mut fire_state = node.fire_state;
+            while tick < self.horizon {
+                let mov = path.last().unwrap();
+                let movs = match self.paths.outgoing.get(&(mov.pos2, mov.vertical_state2)) {
+                    Some(movs) if !movs.is_empty() => movs,
+                    _ => break,
+                };
+                if let Some(weapon) = &self.weapon {
+                    if fire_state.can_fire(tick + root_tick) {
+                        let value = shot_value(mov.pos2, tick);
+                        if value > 0.0 {
+                            shots += value;
+                            fire_state = fire_state.after_shot(tick + root_tick, weapon);
+                        }
+                    }
+                }
+                let mov2 = movs[sample(movs, rand)].clone();
+                tick += mov2.ticks;
+                path.push(mov2);
+            }
+
+            let value = shots - path_cost(&path).unwrap_or(MAX_COST).min(MAX_COST);
+            for index in visited {
+                tree.nodes[index].visits += 1.0;
+                tree.nodes[index].value_sum += value;
+            }
+        }
+        iterations
+    }
+
+    /// Ребра вершины: все ходы графа, с выстрелом и без, если оружие готово
+    fn expand_edges(&self, tree: &mut Tree, index: usize) {
+        let node = &tree.nodes[index];
+        if !node.edges.is_empty() {
+            return;
+        }
+        let can_fire = self.weapon.is_some() && node.fire_state.can_fire(node.tick);
+        let edges = self.paths.outgoing.get(&(node.pos, node.vertical_state))
+            .map(|movs| movs.iter()
+                .flat_map(|mov| {
+                    let fire_options: &[bool] = if can_fire { &[false, true] } else { &[false] };
+                    fire_options.iter().map(move |fire| (mov.clone(), *fire, None))
+                })
+                .collect())
+            .unwrap_or_default();
+        tree.nodes[index].edges = edges;
+    }
+
+    /// UCB1; непосещенные ребра выбираются первыми, среди них - случайно
+    fn select_edge(&self, tree: &Tree, index: usize, rand: &mut Random) -> usize {
+        let node = &tree.nodes[index];
+        let unvisited: Vec<usize> = node.edges.iter()
+            .enumerate()
+            .filter(|(_, (_, _, child))| child.is_none_or(|child| tree.nodes[child].visits == 0.0))
+            .map(|(edge, _)| edge)
+            .collect();
+        if !unvisited.is_empty() {
+            return unvisited[rand.next_u32_bounded(unvisited.len() as u32) as usize];
+        }
+        let log_visits = node.visits.max(1.0).ln();
+        let score = |edge: usize| {
+            let child = &tree.nodes[node.edges[edge].2.unwrap()];
+            child.value_sum / child.visits + EXPLORATION * (log_visits / child.visits).sqrt()
+        };
+        (0..node.edges.len())
+            .max_by(|&a, &b| score(a).partial_cmp(&score(b)).unwrap())
+            .unwrap()
+    }
+
+    /// план по самым посещаемым ребрам
+    pub fn best_plan(&self, tree: &Tree) -> Option<Plan> {
+        let root = &tree.nodes[tree.root];
+        let mut path = vec![make_start_node(root.pos, root.vertical_state)];
+        let mut fire = false;
+        let mut value = None;
+        let mut current = tree.root;
+        loop {
+            let node = &tree.nodes[current];
+            let best = node.edges.iter()
+                .filter_map(|(mov, fire, child)| child.map(|child| (mov, *fire, child)))
+                .filter(|(_, _, child)| tree.nodes[*child].visits > 0.0)
+                .max_by(|a, b| tree.nodes[a.2].visits.partial_cmp(&tree.nodes[b.2].visits).unwrap());
+            let (mov, edge_fire, child) = match best {
+                Some(best) => best,
+                None => break,
+            };
+            if current == tree.root {
+                fire = edge_fire;
+                value = Some(tree.nodes[child].value_sum / tree.nodes[child].visits);
+            }
+            path.push(mov.clone());
+            current = child;
+        }
+        value.map(|value| Plan { path, fire, value })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::path::{ControlResult, Move, MoveType, Paths, TilePos, VerticalState};
+    use crate::rand::Random;
+
+    use super::*;
+
+    const WEAPON: WeaponModel = WeaponModel { fire_ticks: 6, reload_ticks: 60, magazine_size: 2 };
+
+    fn walk(pos1: TilePos, pos2: TilePos) -> Move {
+        Move {
+            typ: if pos2.0 > pos1.0 { MoveType::WalkRight } else { MoveType::WalkLeft },
+            pos1,
+            vertical_state1: VerticalState::Default,
+            pos2,
+            vertical_state2: VerticalState::Default,
+            ticks: 6,
+            control: Rc::new(|_, _| ControlResult::TargetReached),
+        }
+    }
+
+    // коридор из трех клеток: (0, 1) - (1, 1) - (2, 1)
+    fn corridor() -> Paths {
+        let mut paths = Paths::new();
+        for (pos1, pos2) in &[((0, 1), (1, 1)), ((1, 1), (0, 1)), ((1, 1), (2, 1)), ((2, 1), (1, 1))] {
+            let mov = walk(*pos1, *pos2);
+            paths.outgoing.entry((mov.pos1, mov.vertical_state1)).or_default().push(mov.clone());
+            paths.incoming.entry((mov.pos2, mov.vertical_state2)).or_default().push(mov);
+        }
+        paths
+    }
+
+    fn subtree_size(tree: &Tree, node: usize) -> usize {
+        1 + tree.nodes[node].edges.iter().filter_map(|(_, _, child)| *child).map(|child| subtree_size(tree, child)).sum::<usize>()
+    }
+
+    fn planner(paths: &Paths) -> Planner<'_> {
+        Planner { paths, weapon: Some(WEAPON), horizon: 12, iterations: 500, time_limit_ms: None }
+    }
+
+    // поиск, где выгодно дойти до (2, 1), а выстрел с (0, 1) на первом тике стоит shot
+    fn search(paths: &Paths, tree: &mut Tree, shot: f64) {
+        let mut rand = Random::new(1);
+        planner(paths).search(tree, &mut rand,
+                              &|movs, rand| rand.next_u32_bounded(movs.len() as u32) as usize,
+                              &mut |path| Some((2 - path.last().unwrap().pos2.0) as f64 * 10.0),
+                              &mut |pos, tick| if pos == (0, 1) && tick == 0 { shot } else { 0.0 });
+    }
+
+    #[test]
+    fn fire_state_reloads_after_magazine() {
+        let state = FireState { ready_tick: 0, magazine: 2 };
+        assert!(state.can_fire(0));
+        let state = state.after_shot(0, &WEAPON);
+        assert_eq!(state, FireState { ready_tick: 6, magazine: 1 });
+        assert!(!state.can_fire(5));
+        assert!(state.can_fire(6));
+        let state = state.after_shot(6, &WEAPON);
+        assert_eq!(state, FireState { ready_tick: 66, magazine: 2 });
+        assert!(!FireState { ready_tick: 0, magazine: 0 }.can_fire(100));
+    }
+
+    #[test]
+    fn select_edge_prefers_unvisited_then_ucb() {
+        let paths = corridor();
+        let planner = planner(&paths);
+        let mut tree = Tree::new((1, 1), VerticalState::Default, FireState { ready_tick: 100, magazine: 2 });
+        planner.expand_edges(&mut tree, 0);
+        // оружие не готово: по ребру на ход, без выстрела
+        assert_eq!(tree.nodes[0].edges.len(), 2);
+        let mut rand = Random::new(1);
+        for (edge, (visits, value_sum)) in [(0, (1.0, -10.0)), (1, (0.0, 0.0))].iter() {
+            let mov = tree.nodes[0].edges[*edge].0.clone();
+            tree.nodes.push(Node { pos: mov.pos2, vertical_state: mov.vertical_state2, tick: mov.ticks, fire_state: tree.nodes[0].fire_state,
+                                   edges: Vec::new(), visits: *visits, value_sum: *value_sum });
+            tree.nodes[0].edges[*edge].2 = Some(tree.nodes.len() - 1);
+        }
+        tree.nodes[0].visits = 1.0;
+        // непосещенное ребро первым
+        assert_eq!(planner.select_edge(&tree, 0, &mut rand), 1);
+
+        // поровну посещений - выше среднее
+        tree.nodes[2].visits = 1.0;
+        tree.nodes[2].value_sum = -5.0;
+        tree.nodes[0].visits = 2.0;
+        assert_eq!(planner.select_edge(&tree, 0, &mut rand), 1);
+
+        // среднее чуть хуже, но посещений намного меньше - исследуем
+        tree.nodes[1].visits = 100.0;
+        tree.nodes[1].value_sum = -400.0;
+        tree.nodes[2].visits = 1.0;
+        tree.nodes[2].value_sum = -10.0;
+        tree.nodes[0].visits = 101.0;
+        assert_eq!(planner.select_edge(&tree, 0, &mut rand), 1);
+    }
+
+    #[test]
+    fn search_prefers_target_and_shot() {
+        let paths = corridor();
+        let ready = FireState { ready_tick: 0, magazine: 2 };
+
+        let mut tree = Tree::new((0, 1), VerticalState::Default, ready);
+        search(&paths, &mut tree, 0.0);
+        let plan = planner(&paths).best_plan(&tree).unwrap();
+        assert_eq!(plan.path[1].pos2, (1, 1));
+        assert_eq!(plan.path.last().unwrap().pos2, (2, 1));
+
+        let mut tree = Tree::new((0, 1), VerticalState::Default, ready);
+        search(&paths, &mut tree, 100.0);
+        let plan = planner(&paths).best_plan(&tree).unwrap();
+        assert!(plan.fire);
+        assert!(plan.value > 0.0);
+    }
+
+    #[test]
+    fn reroot_keeps_subtree_with_matching_fire_state() {
+        let paths = corridor();
+        let ready = FireState { ready_tick: 0, magazine: 2 };
+
+        // без выстрела: состояние оружия в (1, 1) то же, поддерево переиспользуется с уменьшенной статистикой
+        let mut tree = Tree::new((0, 1), VerticalState::Default, ready);
+        search(&paths, &mut tree, 0.0);
+        let child = tree.nodes[0].edges.iter()
+            .filter_map(|(_, fire, child)| child.filter(|&child| !fire && tree.nodes[child].pos == (1, 1)))
+            .max_by(|&a, &b| tree.nodes[a].visits.partial_cmp(&tree.nodes[b].visits).unwrap())
+            .unwrap();
+        let child_visits = tree.nodes[child].visits;
+        let child_subtree = subtree_size(&tree, child);
+        assert!(child_subtree < tree.nodes.len());
+        let tree = tree.reroot((1, 1), VerticalState::Default, ready).unwrap();
+        // остаются только вершины поддерева, корень - первая
+        assert_eq!(tree.root, 0);
+        assert_eq!(tree.nodes.len(), child_subtree);
+        assert_eq!(subtree_size(&tree, tree.root), child_subtree);
+        assert_eq!(tree.nodes[tree.root].pos, (1, 1));
+        assert_eq!(tree.root_visits(), child_visits * REUSE_DECAY);
+        // переиспользованное дерево можно сдвинуть еще раз
+        let tree = tree.reroot((2, 1), VerticalState::Default, ready).unwrap();
+        assert_eq!(tree.nodes[tree.root].pos, (2, 1));
+
+        // в клетку, куда из корня нет хода, не переходим
+        let mut tree = Tree::new((0, 1), VerticalState::Default, ready);
+        search(&paths, &mut tree, 0.0);
+        assert!(tree.reroot((2, 1), VerticalState::Default, ready).is_none());
+
+        // выстрелили: берется ветка с выстрелом, не самая посещаемая
+        let mut tree = Tree::new((0, 1), VerticalState::Default, ready);
+        search(&paths, &mut tree, 0.0);
+        let fired = FireState { ready_tick: 0, magazine: 1 };
+        let tree = tree.reroot((1, 1), VerticalState::Default, fired).unwrap();
+        assert_eq!(tree.nodes[tree.root].fire_state, FireState { ready_tick: WEAPON.fire_ticks, magazine: 1 });
+
+        // состояния оружия, как в игре, в дереве нет
+        let mut tree = Tree::new((0, 1), VerticalState::Default, ready);
+        search(&paths, &mut tree, 0.0);
+        assert!(tree.reroot((1, 1), VerticalState::Default, FireState { ready_tick: 30, magazine: 2 }).is_none());
+    }
+}
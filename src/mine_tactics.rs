@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+
+use model::{Game, Item, Level, MineState, Tile, Unit};
+
+use crate::fnv_hash::FnvHashMap;
+use crate::path::{DistanceMap, TilePos, VerticalState};
+use crate::visibility::Visibility;
+
+// запас в тиках, в пределах которого клетка считается лежащей на кратчайшем пути
+const PATH_SLACK: i32 = 6;
+// ширина "слоя" кратчайших путей в тиках: если в слое одна клетка, через нее проходят все пути
+const CHOKEPOINT_LAYER: i32 = 6;
+// не уходим ставить мину дальше этого
+const MAX_PLANT_TICKS: i32 = 120;
+
+const PATH_SCORE: f64 = 50.0;
+const CHOKEPOINT_SCORE: f64 = 50.0;
+const LOOT_SCORE: f64 = 60.0;
+const LADDER_SCORE: f64 = 20.0;
+const LURE_SCORE: f64 = 80.0;
+// противник видит мину и может ее обойти или подорвать выстрелом
+const VISIBLE_PENALTY: f64 = 40.0;
+const TICK_COST: f64 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MinePlanKind {
+    // дойти до клетки и поставить мину
+    Plant,
+    // встать так, чтобы противник по дороге к нам прошел через нашу мину
+    Lure,
+}
+
+#[derive(Clone, Debug)]
+pub struct MinePlan {
+    pub kind: MinePlanKind,
+    pub target: TilePos,
+    pub score: f64,
+}
+
+/// можно ли поставить мину, стоя в клетке pos (под ногами стена или платформа, не на лестнице)
+pub fn can_plant_mine(pos: TilePos, level: &Level) -> bool {
+    if level.tiles[pos.0 as usize][pos.1 as usize] == Tile::Ladder || level.tiles[pos.0 as usize][(pos.1 + 1) as usize] == Tile::Ladder {
+        return false;
+    }
+    matches!(level.tiles[pos.0 as usize][(pos.1 - 1) as usize], Tile::Wall | Tile::Platform)
+}
+
+/// Карты расстояний для выбора мин (граф односторонний: упасть быстро, запрыгнуть долго, так что направление важно)
+pub struct MineMaps<'a> {
+    // от нас до клеток
+    pub my_map: &'a DistanceMap,
+    // от клеток до нас
+    pub to_me_map: &'a DistanceMap,
+    // от противников (по id) до клеток
+    pub enemy_maps: &'a BTreeMap<i32, DistanceMap>,
+    // от клеток до предметов
+    pub loot_maps: &'a FnvHashMap<TilePos, DistanceMap>,
+    // от клеток со своими минами до клеток
+    pub mine_maps: &'a FnvHashMap<TilePos, DistanceMap>,
+}
+
+/// Выбор места для мины (не самоубийства): узкие места на кратчайшем пути противника к нам,
+/// клетки рядом с низом лестниц на этом пути и предметы, к которым идет противник.
+/// Мина должна успеть взвестись до прихода противника (mine_prepare_time).
+/// Если своя мина уже стоит - ищем клетку, идя к которой противник пройдет через радиус срабатывания.
+pub fn choose_mine_plan(unit: &Unit, game: &Game, maps: &MineMaps, visibility: &mut Visibility) -> Option<MinePlan> {
+    let MineMaps { my_map, to_me_map, enemy_maps, loot_maps: loot_distance_map, mine_maps } = *maps;
+    let level = &game.level;
+    let properties = &game.properties;
+    let pos = (unit.position.x as isize, unit.position.y as isize);
+    let prepare_ticks = (properties.mine_prepare_time * properties.ticks_per_second).ceil() as i32;
+    let enemies: Vec<&Unit> = game.units.iter()
+        .filter(|unit2| unit2.player_id != unit.player_id && enemy_maps.contains_key(&unit2.id))
+        .collect();
+    let ticks = |map: &DistanceMap, pos2: TilePos| map.get(&(pos2, VerticalState::Default)).copied();
+
+    let mut best: Option<MinePlan> = None;
+
+    // свои мины, которые уже стоят: заманиваем на них
+    for mine in game.mines.iter().filter(|mine| mine.player_id == unit.player_id) {
+        if mine.state != MineState::Idle && mine.state != MineState::Preparing {
+            continue;
+        }
+        let mine_pos = (mine.position.x as isize, mine.position.y as isize);
+        let mine_map = match mine_maps.get(&mine_pos) {
+            Some(mine_map) => mine_map,
+            None => continue,
+        };
+        // от взрыва своей мины надо держаться подальше
+        let safe_distance = mine.explosion_params.radius + properties.unit_size.y;
+        for enemy in &enemies {
+            let enemy_map = &enemy_maps[&enemy.id];
+            let enemy_to_mine = match ticks(enemy_map, mine_pos) {
+                Some(enemy_to_mine) => enemy_to_mine,
+                None => continue,
+            };
+            for (&(pos2, vertical_state), &our_ticks) in my_map {
+                if vertical_state != VerticalState::Default || our_ticks > MAX_PLANT_TICKS || our_ticks >= enemy_to_mine {
+                    continue;
+                }
+                if ((pos2.0 - mine_pos.0).abs() as f64) < safe_distance && ((pos2.1 - mine_pos.1).abs() as f64) < safe_distance {
+                    continue;
+                }
+                // мина лежит на кратчайшем пути противника к pos2
+                let on_path = match (ticks(mine_map, pos2), ticks(enemy_map, pos2)) {
+                    (Some(mine_to_pos), Some(enemy_to_pos)) => enemy_to_mine + mine_to_pos <= enemy_to_pos + PATH_SLACK,
+                    _ => false,
+                };
+                if on_path {
+                    offer(&mut best, MinePlan { kind: MinePlanKind::Lure, target: pos2, score: LURE_SCORE - our_ticks as f64 * TICK_COST });
+                }
+            }
+        }
+    }
+
+    // ставить нечего
+    if unit.mines == 0 {
+        return best;
+    }
+    for enemy in &enemies {
+        let enemy_map = &enemy_maps[&enemy.id];
+        let enemy_pos = (enemy.position.x as isize, enemy.position.y as isize);
+        let enemy_weapon = enemy.weapon.as_ref().map(|weapon| weapon.typ.clone());
+        let enemy_to_us = ticks(enemy_map, pos);
+
+        // клетки кратчайшего пути противника к нам, по слоям расстояния от противника
+        let mut path_tiles: FnvHashMap<TilePos, i32> = FnvHashMap::default();
+        let mut layers: FnvHashMap<i32, usize> = FnvHashMap::default();
+        if let Some(enemy_to_us) = enemy_to_us {
+            for (&(pos2, vertical_state), &to_us_ticks) in to_me_map {
+                if vertical_state != VerticalState::Default {
+                    continue;
+                }
+                if let Some(enemy_ticks) = ticks(enemy_map, pos2) {
+                    if enemy_ticks + to_us_ticks <= enemy_to_us + PATH_SLACK {
+                        path_tiles.insert(pos2, enemy_ticks);
+                        *layers.entry(enemy_ticks / CHOKEPOINT_LAYER).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+
+        // предмет, за которым, скорее всего, идет противник
+        let enemy_loot = game.loot_boxes.iter()
+            .filter(|loot| match loot.item {
+                Item::HealthPack { .. } => enemy.health < properties.unit_max_health,
+                Item::Weapon { .. } => enemy.weapon.is_none(),
+                Item::Mine {} => false,
+            })
+            .filter_map(|loot| {
+                let loot_pos = (loot.position.x as isize, loot.position.y as isize);
+                loot_distance_map.get(&loot_pos)
+                    .and_then(|map| map.get(&(enemy_pos, VerticalState::Default)))
+                    .map(|enemy_ticks| (loot_pos, *enemy_ticks))
+            })
+            .min_by_key(|(_, enemy_ticks)| *enemy_ticks);
+
+        let mut candidates: Vec<TilePos> = path_tiles.keys().copied().collect();
+        // при равной оценке выбор не должен зависеть от порядка в хеш-таблице
+        candidates.sort();
+        if let Some((loot_pos, _)) = enemy_loot {
+            candidates.push(loot_pos);
+        }
+        for pos2 in candidates {
+            if !can_plant_mine(pos2, level) {
+                continue;
+            }
+            let (our_ticks, enemy_ticks) = match (ticks(my_map, pos2), ticks(enemy_map, pos2)) {
+                (Some(our_ticks), Some(enemy_ticks)) => (our_ticks, enemy_ticks),
+                _ => continue,
+            };
+            // мина должна взвестись до прихода противника
+            if our_ticks > MAX_PLANT_TICKS || our_ticks + prepare_ticks >= enemy_ticks {
+                continue;
+            }
+            let mut score = 0.0;
+            if let Some(layer_ticks) = path_tiles.get(&pos2) {
+                score += PATH_SCORE;
+                if layers[&(layer_ticks / CHOKEPOINT_LAYER)] == 1 {
+                    score += CHOKEPOINT_SCORE;
+                }
+                if is_near_ladder_bottom(pos2, level) {
+                    score += LADDER_SCORE;
+                }
+            }
+            if enemy_loot.map(|(loot_pos, _)| loot_pos == pos2).unwrap_or(false) {
+                score += LOOT_SCORE;
+            }
+            if let Some(weapon_type) = &enemy_weapon {
+                if visibility.is_exposed(pos2, enemy_pos, weapon_type, level, properties) {
+                    score -= VISIBLE_PENALTY;
+                }
+            }
+            score -= our_ticks as f64 * TICK_COST;
+            offer(&mut best, MinePlan { kind: MinePlanKind::Plant, target: pos2, score });
+        }
+    }
+    best
+}
+
+fn offer(best: &mut Option<MinePlan>, plan: MinePlan) {
+    if plan.score > 0.0 && best.as_ref().map(|best| best.score < plan.score).unwrap_or(true) {
+        *best = Some(plan);
+    }
+}
+
+/// рядом с низом лестницы: спускающийся противник встает на соседнюю клетку
+fn is_near_ladder_bottom(pos: TilePos, level: &Level) -> bool {
+    [-1, 1].iter().any(|dx| {
+        let x = pos.0 + dx;
+        x >= 0 && (x as usize) < level.width() &&
+            level.tiles[x as usize][pos.1 as usize] == Tile::Ladder &&
+            level.tiles[x as usize][(pos.1 - 1) as usize] != Tile::Ladder
+    })
+}
@@ -1,22 +1,95 @@
-use std::collections::{HashSet, VecDeque};
-use std::collections::hash_map::Entry::{Occupied, Vacant};
-use std::rc::Rc;
+use std::collections::{BTreeMap, HashSet, VecDeque};
 use std::time::Instant;
 
-use model::{Bullet, ColorF32, Game, Level, LootBox, Properties, Tile, Unit, Vec2F32, Vec2F64, WeaponType};
+use model::{ColorF32, Game, Properties, Unit, Vec2F32, Vec2F64, Weapon, WeaponType};
 
+use crate::aim::{choose_aim, delta_angle, hit_chance, spread_after};
+use crate::bullets::{bullet_end, Bullets, BulletsState};
+use crate::danger::DangerMap;
+use crate::collision::swept_aabb;
+use crate::explosion::{chain_explosions, explosions_damage, mine_center, Explosion};
 use crate::fnv_hash::FnvHashMap;
-use crate::path::{ControlResult, get_movements, get_recover_move, Move, MoveAction, MoveType, TilePos, VerticalState, get_mine_suicide_move};
+use crate::goal::{choose_goal, suicide_damage, Goal, LootTicks};
+use crate::mcts::{FireState, Planner, Tree, WeaponModel};
+use crate::mine_tactics::{can_plant_mine, choose_mine_plan, MineMaps, MinePlan, MinePlanKind};
+use crate::path::{ControlResult, DistanceMap, get_movements, get_recover_move, make_start_node, Move, MoveAction, MoveType, Paths, TilePos, VerticalState, get_mine_suicide_move, smooth_control};
 use crate::rand::Random;
 use crate::non_nan_f64::NonNan;
+use crate::recovery::{classify_failure, plan_recovery, Failure};
+use crate::unit_sim::{position_at, vertical_state, UnitSim};
+use crate::visibility::Visibility;
+use crate::weapon::{should_reload, should_swap_weapon};
 
+// зерно случайных чисел по умолчанию (переопределяется через AICUP_SEED)
+pub const DEFAULT_SEED: u64 = 98754;
 const USE_MINE_SUICIDE: bool = true;
+// ставить мины заранее на пути противника и заманивать на свои мины
+const USE_MINE_TACTICS: bool = true;
+// штраф за конец пути на простреливаемой клетке, пока мы не можем стрелять
+const COVER_COST: f64 = 100.0;
+// сколько секунд перезарядки считаем "не можем стрелять"
+const COVER_FIRE_TIMER: f64 = 0.5;
+// штраф за каждый тик перезарядки на простреливаемой клетке
+const RELOAD_EXPOSURE_COST: f64 = 2.0;
+// не тратим пули (без взрыва), если вероятность попасть в ближайшего противника меньше
+const MIN_HIT_CHANCE: f64 = 0.05;
+// на сколько стандартных отклонений урона своим ожидаемый урон противнику должен его превышать
+const SHOT_RISK: f64 = 1.0;
+// на сколько направлений делим разброс при оценке выстрела
+const SHOT_SAMPLES: usize = 16;
+// уклоняться от пуль точной моделью движения, в обход плана по клеткам
+const USE_DODGE: bool = true;
+// на сколько тиков вперед проверяем попадания и держим действие уклонения
+const DODGE_TICKS: i32 = 10;
+// шагов модели движения на тик при уклонении (на сервере updates_per_tick)
+const DODGE_MICROTICKS: usize = 10;
+// шагов модели движения на тик при прогоне планов
+const PATH_MICROTICKS: usize = 5;
+// на сколько тиков предсказываем движение своих при оценке выстрела
+const SHOT_PREDICTION_TICKS: i32 = 120;
+// сколько самых вероятных положений противника учитываем
+const ENEMY_POSITIONS: usize = 4;
+// рисовать карту опасности (урон по клеткам от пуль, ракет и мин)
+const DRAW_DANGER_MAP: bool = false;
+// оценивать урон на случайных путях по карте опасности вместо прогона пуль по каждому ходу
+const USE_DANGER_MAP: bool = false;
+// на сколько тиков вперед и с каким шагом считаем карту опасности
+const DANGER_TICKS: i32 = 90;
+const DANGER_BUCKET_TICKS: i32 = 6;
+// случайные пути: следующий ход выбирается softmax по (тики хода + насколько расстояние до цели отличается от нужного)
+// с температурой в тиках, с вероятностью SAMPLING_EPSILON - равновероятно
+const USE_BIASED_SAMPLING: bool = true;
+const SAMPLING_TEMPERATURE: f64 = 10.0;
+const SAMPLING_EPSILON: f64 = 0.2;
+// на каком расстоянии (в тиках пути до ближайшего противника) держимся, когда другой цели нет
+const ENEMY_DISTANCE: i32 = 50;
+// сколько путей строим от лучшего пути прошлого тика; кроме первого, у них обрезается случайный хвост и достраивается заново
+const OLD_PATH_SAMPLES: usize = 10;
+const MUTATE_OLD_PATH: bool = true;
+// планирование поиском по дереву (MCTS) по ходам графа и выбору стрелять/ждать вместо случайных путей
+const USE_MCTS: bool = false;
+// итераций поиска на игрока за тик
+const MCTS_ITERATIONS: usize = 300;
+// лимит времени стратегии на всю игру, мс
+const GAME_TIME_BUDGET_MS: u128 = 40000;
+// доля оставшегося на тик времени, которую отдаем поиску
+const MCTS_TIME_SHARE: f64 = 0.5;
+// вес ожидаемых очков за выстрел (урон и убийство), в единицах стоимости пути: урон нам стоит 100 за единицу,
+// то есть очко противника весит столько же, сколько наше
+const MCTS_SHOT_WEIGHT: f64 = 100.0;
+// через сколько тиков берем предсказанное положение противника (дальше 20 тиков модель не смотрит)
+const MCTS_ENEMY_BUCKET_TICKS: i32 = 10;
 
 pub struct MyStrategy {
     total_time: u128,
+    // ограничивать поиск по времени (решения зависят от скорости машины, при проверке повторов выключено)
+    time_limits: bool,
     rand: Random,
     paths: Paths,
     distance_map: FnvHashMap<TilePos, FnvHashMap<(TilePos, VerticalState), i32>>,
+    // расстояния от клеток со своими минами (мины не двигаются, карты считаем один раз)
+    mine_maps: FnvHashMap<TilePos, DistanceMap>,
+    visibility: Option<Visibility>,
     unit1_data: UnitData,
     unit2_data: UnitData,
     last_enemy_state: FnvHashMap<i32, Unit>,
@@ -29,24 +102,51 @@ struct UnitData {
     path: Vec<Move>,
     path_start_tick: i32,
     last_position: Vec2F64,
+    mine_plan: Option<MinePlan>,
+    // последний план уводит от противника (в укрытие или за аптечкой)
+    retreating: bool,
+    // свой поток случайных чисел: решения одного игрока не сдвигают случайность другого
+    rand: Random,
+    // дерево поиска прошлого планирования, переиспользуется с вершины, где мы оказались
+    mcts_tree: Option<Tree>,
+    // ход, на котором поиск решил не стрелять: не стреляем, пока он выполняется
+    hold_fire: Option<Move>,
+}
+
+impl UnitData {
+    /// номер текущего хода в плане (ход не из плана - recover, уклонение, подрыв)
+    fn move_index(&self) -> Option<usize> {
+        self.move_.as_ref()
+            .and_then(|mov| self.path.iter()
+                .skip(1)
+                .position(|mov2| mov2.typ == mov.typ && mov2.pos1 == mov.pos1 && mov2.pos2 == mov.pos2))
+            .map(|index| index + 1)
+    }
 }
 
 impl MyStrategy {
-    pub fn new() -> Self {
+    /// Все случайные решения выводятся из seed, так что при одинаковых сообщениях сервера действия совпадают
+    /// (если time_limits выключен: иначе глубина поиска зависит от времени).
+    pub fn new(seed: u64, time_limits: bool) -> Self {
         Self {
             total_time: 0,
-            rand: Random::new(98754),
-            paths: Paths {
-                outgoing: FnvHashMap::default(),
-                incoming: FnvHashMap::default(),
-            },
+            time_limits,
+            rand: Random::new(seed),
+            paths: Paths::new(),
             distance_map: FnvHashMap::default(),
+            mine_maps: FnvHashMap::default(),
+            visibility: None,
             unit1_data: UnitData {
                 id: -1,
                 move_: None,
                 path: Vec::new(),
                 path_start_tick: 0,
                 last_position: Vec2F64 { x: -1.0, y: -1.0 },
+                mine_plan: None,
+                retreating: false,
+                rand: Random::new(seed),
+                mcts_tree: None,
+                hold_fire: None,
             },
             unit2_data: UnitData {
                 id: -1,
@@ -54,6 +154,11 @@ impl MyStrategy {
                 path: Vec::new(),
                 path_start_tick: 0,
                 last_position: Vec2F64 { x: -1.0, y: -1.0 },
+                mine_plan: None,
+                retreating: false,
+                rand: Random::new(seed),
+                mcts_tree: None,
+                hold_fire: None,
             },
             last_enemy_state: FnvHashMap::default(),
         }
@@ -71,8 +176,10 @@ impl MyStrategy {
         if game.current_tick == 0 {
             if self.unit1_data.id == -1 {
                 self.unit1_data.id = unit.id;
+                self.unit1_data.rand = self.rand.sub_stream(unit.id as u64);
             } else if self.unit2_data.id == -1 {
                 self.unit2_data.id = unit.id;
+                self.unit2_data.rand = self.rand.sub_stream(unit.id as u64);
             }
             for unit2 in &game.units {
                 if unit2.player_id != unit.player_id {
@@ -93,7 +200,7 @@ impl MyStrategy {
             let pos = tile_pos(unit.position.clone());
             let vertical_state = MyStrategy::get_vertical_state(unit, game);
             if self.paths.outgoing.get(&(pos, vertical_state)).is_none() {
-                self.paths.update_paths(pos, vertical_state, &game);
+                self.paths.update_paths(pos, vertical_state, &game.level, &game.properties);
             }
         }
 //        self.draw_all_movements2(debug);
@@ -105,35 +212,65 @@ impl MyStrategy {
             let start2 = Instant::now();
             for loot in &game.loot_boxes {
                 let pos = tile_pos(loot.position);
-                let map = MyStrategy::build_all_paths(pos, paths);
+                let map = paths.build_all_paths(pos);
                 self.distance_map.insert(pos, map);
             }
             println!("build_all_paths count {}, {} ms", self.distance_map.len(), start2.elapsed().as_millis());
         }
 
         let distance_map = &self.distance_map;
+        let mine_maps = &mut self.mine_maps;
+
+        if self.visibility.is_none() {
+            self.visibility = Some(Visibility::new(&game.level));
+        }
+        let visibility = self.visibility.as_mut().unwrap();
 
-        let suicide_damage = unit.mines * game.properties.mine_explosion_params.damage +
-            if unit.weapon.is_some() && unit.weapon.as_ref().unwrap().typ == WeaponType::RocketLauncher { unit.weapon.as_ref().unwrap().params.explosion.as_ref().unwrap().damage } else { 0 };
+        let suicide_damage = suicide_damage(unit, game);
+
+        // пули и мины на этот тик: для планирования, уклонения и карты опасности
+        let bullets = Bullets::new(game);
+
+        let danger_map = if DRAW_DANGER_MAP || USE_DANGER_MAP {
+            let start = Instant::now();
+            let danger_map = DangerMap::new(paths, unit.id, &bullets, DANGER_TICKS, DANGER_BUCKET_TICKS, &game.properties);
+            if !danger_map.is_empty() {
+                println!("danger map {} ms", start.elapsed().as_millis());
+            }
+            Some(danger_map)
+        } else {
+            None
+        };
 
         if unit_data.move_.is_some() && unit_data.last_position.x == unit.position.x && unit_data.last_position.y == unit.position.y &&
             unit_data.move_.as_ref().unwrap().typ != MoveType::MineSuicide {
-            // застряли, скорее всего на другом игроке, нужен новый план
-            println!("got stuck");
+            // застряли: на другом игроке нужен новый план, иначе возвращаемся на граф
+            let failure = classify_failure(unit_data.move_.as_ref().unwrap(), unit, true, game);
+            println!("got stuck: {:?} {:?} at {:?}", failure, unit_data.move_, unit.position);
             unit_data.path.clear();
-            unit_data.move_ = None;
+            unit_data.move_ = if failure == Failure::BlockedByUnit { None } else { MyStrategy::recovery_move(unit, paths, game) };
         }
         unit_data.last_position = unit.position.clone();
 
+        // перезарядку решаем до планирования: пока она идет, стрелять не можем
+        let reload = should_reload(unit, game, unit_data.retreating) &&
+            unit_data.move_.as_ref().map(|mov| mov.typ != MoveType::MineSuicide).unwrap_or(true);
+        if reload {
+            println!("reload");
+        }
+
         let mut move_action: Option<MoveAction> = None;
+        let next_move = unit_data.move_index().and_then(|index| unit_data.path.get(index + 1));
         let new_move = unit_data.move_.as_ref().and_then(|mr| {
-            match (mr.control)(unit.position, MyStrategy::get_vertical_state(unit, game)) {
+            match smooth_control(mr, next_move, unit.position, MyStrategy::get_vertical_state(unit, game)) {
                 ControlResult::TargetReached => None,
                 ControlResult::Recover => {
-                    let recover_move = get_recover_move();
+                    let failure = classify_failure(mr, unit, false, game);
+                    println!("move failed: {:?} {:?} at {:?} {:?}", failure, mr, unit.position, unit.jump_state);
+                    let recover_move = MyStrategy::recovery_move(unit, paths, game).unwrap_or_else(get_recover_move);
                     move_action = match (recover_move.control)(unit.position, MyStrategy::get_vertical_state(unit, game)) {
                         ControlResult::TargetReached => None,
-                        ControlResult::Recover => unreachable!(),
+                        ControlResult::Recover => None,
                         ControlResult::MoveAction(move_action2) => Some(move_action2),
                     };
                     Some(recover_move)
@@ -151,7 +288,7 @@ impl MyStrategy {
             unit_data.move_ = new_move;
         }
         if unit_data.move_.is_some() && unit_data.move_.as_ref().unwrap().typ == MoveType::MineSuicide &&
-            !MyStrategy::suicide_is_effective(unit.position, unit.player_id, suicide_damage, &game) {
+            !MyStrategy::suicide_is_effective(unit.position, unit, game) {
             // выжили, или противник убежал
             unit_data.path.clear();
             unit_data.move_ = None;
@@ -161,11 +298,12 @@ impl MyStrategy {
 
             unit_data.path.clear();
             unit_data.move_ = None;
+            unit_data.hold_fire = None;
 
             // mine suicide
-            if USE_MINE_SUICIDE && unit.weapon.is_some() && unit.mines > 0 && MyStrategy::can_plant_mine(tile_pos(unit.position), &game.level) &&
+            if USE_MINE_SUICIDE && unit.weapon.is_some() && unit.mines > 0 && can_plant_mine(tile_pos(unit.position), &game.level) &&
                 unit.weapon.as_ref().unwrap().fire_timer.unwrap_or(0.0) <= 1.0 / game.properties.ticks_per_second &&
-                MyStrategy::suicide_is_effective(unit.position, unit.player_id, suicide_damage, &game) {
+                MyStrategy::suicide_is_effective(unit.position, unit, game) {
                 unit_data.move_ = Some(get_mine_suicide_move());
                 move_action = match (unit_data.move_.as_ref().unwrap().control)(unit.position, MyStrategy::get_vertical_state(unit, game)) {
                     ControlResult::TargetReached => unreachable!(),
@@ -181,64 +319,240 @@ impl MyStrategy {
                     loot_map.insert(tile_pos(loot.position), loot);
                 }
 
-                fn is_weapon(loot: &LootBox) -> bool { if let model::Item::Weapon { .. } = loot.item { true } else { false } }
-                fn is_health(loot: &LootBox) -> bool { if let model::Item::HealthPack { .. } = loot.item { true } else { false } }
-                fn is_mine(loot: &LootBox) -> bool { if let model::Item::Mine { .. } = loot.item { true } else { false } }
-
                 let max_ticks = 60;
                 let mut best_cost = std::f64::MAX;
                 let mut best_old = std::f64::MAX;
                 let mut best_used_old = false;
 
-                let mut enemy_distance_map = FnvHashMap::default();
+                // по id противника, порядок обхода не зависит от хешей
+                let mut enemy_distance_map = BTreeMap::new();
                 game.units
                     .iter()
                     .filter(|unit2| unit2.player_id != unit.player_id)
                     .for_each(|unit2| {
                         let pos = tile_pos(unit2.position);
-                        let map = MyStrategy::build_all_paths(pos, &paths);
+                        let map = paths.build_all_paths(pos);
                         enemy_distance_map.insert(unit2.id, map);
                     });
 
-                let need_weapon = unit.weapon.is_none() && game.loot_boxes.iter().any(|loot| is_weapon(loot));
-                let need_health = unit.health < game.properties.unit_max_health && game.loot_boxes.iter().any(|loot| is_health(loot));
-                let simple_target_distance_map = {
-                    if need_weapon {
-                        game.loot_boxes.iter()
-                            .filter(|loot| is_weapon(loot))
-                            .map(|loot| tile_pos(loot.position))
-                            .min_by_key(|pos2| distance_map[pos2].get(&(pos, vertical_state)).unwrap_or(&std::i32::MAX))
-                            .map(|pos2| &distance_map[&pos2])
-                            .unwrap()
-                    } else if need_health {
-                        game.loot_boxes.iter()
-                            .filter(|loot| is_health(loot))
-                            .map(|loot| tile_pos(loot.position))
-                            .min_by_key(|pos2| distance_map[pos2].get(&(pos, vertical_state)).unwrap_or(&std::i32::MAX))
-                            .map(|pos2| &distance_map[&pos2])
+                // вооруженные противники и их клетки, для оценки укрытий
+                let armed_enemies: Vec<(TilePos, WeaponType)> = game.units
+                    .iter()
+                    .filter(|unit2| unit2.player_id != unit.player_id)
+                    .filter_map(|unit2| unit2.weapon.as_ref().map(|weapon| (tile_pos(unit2.position), weapon.typ.clone())))
+                    .collect();
+                let need_cover = unit.weapon.is_none() || reload ||
+                    unit.weapon.as_ref().and_then(|weapon| weapon.fire_timer).unwrap_or(0.0) > COVER_FIRE_TIMER;
+                // сколько тиков от начала пути мы перезаряжаемся и не можем ответить
+                let reload_ticks = match &unit.weapon {
+                    Some(weapon) if reload => (weapon.params.reload_time * game.properties.ticks_per_second) as i32,
+                    Some(weapon) if weapon.fire_timer.unwrap_or(0.0) > weapon.params.fire_rate =>
+                        (weapon.fire_timer.unwrap() * game.properties.ticks_per_second) as i32,
+                    _ => 0,
+                };
+
+                // выбор цели: оружие, аптечка, мины или противник
+                // предметы, до которых противник, которому они нужны, дойдет раньше нас, не рассматриваем
+                let loot_ticks: Vec<LootTicks> = game.loot_boxes.iter()
+                    .filter_map(|loot| {
+                        let map = distance_map.get(&tile_pos(loot.position))?;
+                        let ticks = *map.get(&(pos, vertical_state))?;
+                        let enemy_ticks = game.units.iter()
+                            .filter(|unit2| unit2.player_id != unit.player_id)
+                            .filter_map(|unit2| {
+                                let pos2 = tile_pos(unit2.position);
+                                map.get(&(pos2, MyStrategy::get_vertical_state(unit2, game)))
+                                    .or_else(|| map.get(&(pos2, VerticalState::Default)))
+                                    .map(|enemy_ticks| (unit2.id, *enemy_ticks))
+                            })
+                            .collect();
+                        Some(LootTicks { loot, ticks, enemy_ticks })
+                    })
+                    .filter(|entry| !entry.is_lost(game))
+                    .collect();
+                let (goal, goal_scores) = choose_goal(unit, game, &loot_ticks);
+                println!("goal {:?} {:?}", goal, goal_scores);
+                let need_loot = goal != Goal::Enemy;
+                // если стоим под обстрелом и не можем ответить, быстрый путь ведет в ближайшее укрытие
+                let cover_distance_map =
+                    if !need_loot && need_cover && visibility.is_exposed_to_any(pos, &armed_enemies, &game.level, &game.properties) {
+                        visibility.nearest_covered_tile(pos, &armed_enemies, |pos2| paths.incoming.contains_key(&(pos2, VerticalState::Default)),
+                                                        &game.level, &game.properties)
+                            .map(|cover_pos| paths.build_all_paths(cover_pos))
+                    } else {
+                        None
+                    };
+                // мины: поставить заранее на пути противника или заманить его на уже стоящую
+                unit_data.mine_plan = None;
+                if USE_MINE_TACTICS && !need_loot && cover_distance_map.is_none() && unit.weapon.is_some() {
+                    let my_map = paths.build_paths_from(pos, vertical_state);
+                    let to_me_map = paths.build_all_paths(pos);
+                    // сколько противнику идти до клеток (если его вершины нет в графе - как будто стоит на земле)
+                    let enemy_from_map: BTreeMap<i32, DistanceMap> = game.units.iter()
+                        .filter(|unit2| unit2.player_id != unit.player_id)
+                        .map(|unit2| {
+                            let pos2 = tile_pos(unit2.position);
+                            let vertical_state2 = Some(MyStrategy::get_vertical_state(unit2, game))
+                                .filter(|vertical_state2| paths.outgoing.contains_key(&(pos2, *vertical_state2)))
+                                .unwrap_or(VerticalState::Default);
+                            (unit2.id, paths.build_paths_from(pos2, vertical_state2))
+                        })
+                        .collect();
+                    for mine in game.mines.iter().filter(|mine| mine.player_id == unit.player_id) {
+                        let mine_pos = tile_pos(mine.position);
+                        mine_maps.entry(mine_pos).or_insert_with(|| paths.build_paths_from(mine_pos, VerticalState::Default));
+                    }
+                    let maps = MineMaps { my_map: &my_map, to_me_map: &to_me_map, enemy_maps: &enemy_from_map, loot_maps: distance_map, mine_maps };
+                    unit_data.mine_plan = choose_mine_plan(unit, game, &maps, visibility);
+                    if let Some(plan) = &unit_data.mine_plan {
+                        println!("mine plan {:?}", plan);
+                    }
+                }
+                unit_data.retreating = goal == Goal::Health || cover_distance_map.is_some();
+                let mine_distance_map = unit_data.mine_plan.as_ref().map(|plan| paths.build_all_paths(plan.target));
+                // карта расстояний до цели и на каком расстоянии от нее хотим оказаться
+                let (simple_target_distance_map, simple_target_dist) = {
+                    if need_loot {
+                        loot_ticks.iter()
+                            .filter(|entry| goal.is_goal_loot(entry.loot))
+                            .min_by_key(|entry| entry.ticks)
+                            .map(|entry| (&distance_map[&tile_pos(entry.loot.position)], 0))
                             .unwrap()
+                    } else if let Some(map) = &cover_distance_map {
+                        (map, 0)
+                    } else if let Some(map) = &mine_distance_map {
+                        (map, 0)
                     } else {
                         game.units.iter()
                             .filter(|unit2| unit2.player_id != unit.player_id)
                             .min_by_key(|unit2| enemy_distance_map[&unit2.id].get(&(pos, vertical_state)).unwrap_or(&std::i32::MAX))
-                            .map(|unit2| &enemy_distance_map[&unit2.id])
+                            .map(|unit2| (&enemy_distance_map[&unit2.id], ENEMY_DISTANCE))
                             .unwrap()
                     }
                 };
 
                 let very_long_dist = 1000000;
 
-                let path_count = 100;
-                'path_loop: for i in 0..path_count {
-                    let bullets = Bullets::new(game);
+                // движение напарника по его плану
+                let teammate_positions: Vec<(i32, Vec<Vec2F64>)> = game.units.iter()
+                    .filter(|unit2| unit2.player_id == unit.player_id && unit2.id != unit.id && unit2.id == other_unit_data.id)
+                    .map(|unit2| (unit2.id, MyStrategy::predict_positions(unit2, other_unit_data, max_ticks * 2, game)))
+                    .collect();
+
+                // с поиском по дереву из путей остается только быстрый
+                let path_count = if USE_MCTS { 1 } else { 100 };
+                // у многих путей общие начала: урон на ходе с теми же положениями, тиком начала и состоянием пуль считаем один раз
+                let mut damage_cache: DamageCache = FnvHashMap::default();
+                let mut damage_calls = 0;
+                // Стоимость пути (меньше - лучше): урон на пути и удаленность конца от цели.
+                // None, если по дороге столкнемся с другим игроком.
+                let mut path_cost = |path: &[Move]| -> Option<f64> {
                     let mut bullets_state = BulletsState::new();
+                    // оценим повреждения на пути
+                    let path_ticks = path[1..].iter().map(|mov| mov.ticks).sum();
+                    let positions = UnitSim::new(unit).follow_path(path, 1, path_ticks, PATH_MICROTICKS, &game.level, &game.properties);
+                    let mut ticks = 0;
+                    let mut damage = 0;
+                    let mut reload_exposure_cost = 0.0;
+                    for mov in &path[1..] {
+                        // окно перезарядки: тики хода, пока перезаряжаемся, на простреливаемой клетке
+                        let exposed_ticks = (reload_ticks - ticks).clamp(0, mov.ticks);
+                        if exposed_ticks > 0 && visibility.is_exposed_to_any(mov.pos2, &armed_enemies, &game.level, &game.properties) {
+                            reload_exposure_cost += exposed_ticks as f64 * RELOAD_EXPOSURE_COST;
+                        }
+                        // проверим, что не столкнемся с другими игроками
+                        for mov_tick in 0..mov.ticks {
+                            let tick = ticks + mov_tick;
+                            let unit_position = position_at(&positions, tick).0;
+                            for unit2 in &game.units {
+                                if unit2.id == unit.id {
+                                    continue;
+                                }
+                                let unit2_position = teammate_positions.iter()
+                                    .find(|(id, _)| *id == unit2.id)
+                                    .map(|(_, positions2)| position_at(positions2, tick).0)
+                                    .unwrap_or(unit2.position);
+                                if (unit_position.x - unit2_position.x).abs() < game.properties.unit_size.x / 2.0 &&
+                                    (unit_position.y - unit2_position.y).abs() < game.properties.unit_size.y / 2.0 {
+//                                    println!("collision {} {:?} {:?}", tick, unit_position, unit2_position);
+                                    return None;
+                                }
+                            }
+                        }
+
+                        if !USE_DANGER_MAP && bullets.need_test(&bullets_state) {
+                            damage_calls += 1;
+                            let move_positions = &positions[ticks as usize..=(ticks + mov.ticks) as usize];
+                            let (new_damage, new_bullets_state) = damage_cache
+                                .entry((position_key(move_positions), ticks, bullets_state))
+                                .or_insert_with_key(|(_, ticks, bullets_state)|
+                                    MyStrategy::calc_damage(move_positions, *ticks, unit.id, bullets_state, &bullets, game))
+                                .clone();
+                            damage += new_damage;
+                            bullets_state = new_bullets_state;
+                        }
+                        ticks += mov.ticks;
+                    }
+                    if USE_DANGER_MAP {
+                        damage = danger_map.as_ref().map(|danger_map| danger_map.path_damage(path)).unwrap_or(0);
+                    }
+
+                    let last_mov = path.last().unwrap();
+                    let damage_cost = damage as f64 * 100.0 + reload_exposure_cost;
+                    let cost =
+                        if need_loot {
+                            if path.iter()
+                                .filter_map(|mov| loot_map.get(&mov.pos2))
+                                .any(|loot| goal.is_goal_loot(loot)) {
+                                // встретили по дороге
+                                damage_cost
+                            } else {
+                                // определяем расстояние ближайшего предмета
+                                let min_ticks = *loot_ticks
+                                    .iter()
+                                    .map(|entry| entry.loot)
+                                    .filter(|loot| goal.is_goal_loot(loot))
+                                    .filter_map(|loot| distance_map.get(&tile_pos(loot.position))
+                                        .and_then(|map| map.get(&(last_mov.pos2, last_mov.vertical_state2))))
+                                    .min()
+                                    .unwrap_or(&very_long_dist);
+
+                                damage_cost + min_ticks as f64
+                            }
+                        } else if let Some(map) = &mine_distance_map {
+                            damage_cost + *map.get(&(last_mov.pos2, last_mov.vertical_state2)).unwrap_or(&very_long_dist) as f64
+                        } else {
+                            let min_dist_to_enemy = path.iter()
+                                .filter_map(|mov| enemy_distance_map.iter().filter_map(|(id, map)| {
+                                    let enemy = game.units
+                                        .iter()
+                                        .find(|unit2| unit2.id == *id)
+                                        .unwrap();
+                                    let fire_timer_ticks = enemy.weapon.as_ref().and_then(|weapon| weapon.fire_timer).unwrap_or(0.0) * game.properties.ticks_per_second;
+                                    map.get(&(mov.pos2, mov.vertical_state2)).map(|dist| dist + fire_timer_ticks as i32)
+                                }).min())
+                                .min()
+                                .unwrap_or(very_long_dist) as f64;
+
+                            // пока перезаряжаемся, лучше стоять в укрытии
+                            let cover_cost = if need_cover && visibility.is_exposed_to_any(last_mov.pos2, &armed_enemies, &game.level, &game.properties) {
+                                COVER_COST
+                            } else {
+                                0.0
+                            };
+
+                            damage_cost + (min_dist_to_enemy - ENEMY_DISTANCE as f64).abs() * 10.0 + cover_cost
+                        };
+                    Some(cost)
+                };
+                for i in 0..path_count {
                     let empty_vec = vec![];
                     let mut path;
                     let mut ticks;
                     let used_old;
                     if i == path_count - 1 {
                         // проверим быстрый путь
-                        path = vec![MyStrategy::make_start_node(pos, vertical_state)];
+                        path = vec![make_start_node(pos, vertical_state)];
                         ticks = 0;
                         used_old = false;
                         while ticks < max_ticks {
@@ -257,14 +571,18 @@ impl MyStrategy {
                             }
                         }
                     } else {
-                        if i < 5 && old_path.len() > 1 && old_path[1].pos2 == pos && old_path[1].vertical_state2 == vertical_state {
+                        if i < OLD_PATH_SAMPLES && old_path.len() > 1 && old_path[1].pos2 == pos && old_path[1].vertical_state2 == vertical_state {
                             // проверим несколько вариантов на основе старого пути
 //                            println!("i old");
                             path = old_path[1..].to_vec();
+                            if MUTATE_OLD_PATH && i > 0 && path.len() > 2 {
+                                let keep = 1 + unit_data.rand.next_u32_bounded(path.len() as u32 - 1) as usize;
+                                path.truncate(keep);
+                            }
                             ticks = path[1..].iter().map(|mov| mov.ticks).sum();
                             used_old = true;
                         } else {
-                            path = vec![MyStrategy::make_start_node(pos, vertical_state)];
+                            path = vec![make_start_node(pos, vertical_state)];
                             ticks = 0;
                             used_old = false;
                         }
@@ -275,49 +593,16 @@ impl MyStrategy {
                             if movs.is_empty() {
                                 break;
                             }
-                            let mov2 = &movs[self.rand.next_u32_bounded(movs.len() as u32) as usize];
+                            let mov2 = &movs[MyStrategy::sample_move(movs, simple_target_distance_map, simple_target_dist, &mut unit_data.rand)];
                             path.push(mov2.clone());
                             ticks += mov2.ticks;
                         }
                     }
 
-                    // оценим повреждения на пути
-                    let mut ticks = 0;
-                    let mut damage = 0;
-                    for mov in &path[1..] {
-                        // проверим, что не столкнемся с другими игроками
-                        for mov_tick in 0..mov.ticks {
-                            let tick = ticks + mov_tick;
-                            let unit_position = MyStrategy::get_unit_position_at_tick(&unit, &path, tick).0;
-                            for unit2 in &game.units {
-                                if unit2.id == unit.id {
-                                    continue;
-                                }
-                                let unit2_position =
-                                    if unit2.player_id == unit.player_id {
-                                        if other_unit_data.id == -1 {
-                                            unit2.position.clone()
-                                        } else {
-                                            assert_eq!(unit2.id, other_unit_data.id);
-                                            let tick2 = tick + game.current_tick - other_unit_data.path_start_tick;
-                                            MyStrategy::get_unit_position_at_tick(&unit2, &other_unit_data.path, tick2).0
-                                        }
-                                    } else {
-                                        unit2.position.clone()
-                                    };
-                                if (unit_position.x - unit2_position.x).abs() < game.properties.unit_size.x / 2.0 &&
-                                    (unit_position.y - unit2_position.y).abs() < game.properties.unit_size.y / 2.0 {
-//                                    println!("collision {} {:?} {:?}", tick, unit_position, unit2_position);
-                                    continue 'path_loop;
-                                }
-                            }
-                        }
-
-                        let (new_damage, new_bullets_state) = MyStrategy::calc_damage(mov, ticks, unit.id, &bullets_state, &bullets, &game);
-                        ticks += mov.ticks;
-                        damage += new_damage;
-                        bullets_state = new_bullets_state;
-                    }
+                    let cost = match path_cost(&path) {
+                        Some(cost) => cost,
+                        None => continue,
+                    };
 
                     // нарисовать путь
 //                    for i in 0..path.len() - 1 {
@@ -347,83 +632,6 @@ impl MyStrategy {
 //                        size: Vec2F32 { x: 0.3, y: 0.3 },
 //                    });
 
-                    let last_mov = path.last().unwrap();
-                    let damage_cost = damage as f64 * 100.0;
-                    let cost =
-                        if need_weapon {
-                            if path.iter()
-                                .filter_map(|mov| loot_map.get(&mov.pos2))
-                                .any(|loot| is_weapon(loot)) {
-                                // встретили по дороге
-                                damage_cost
-                            } else {
-                                // определяем расстояние ближайшего предмета
-                                let min_ticks = *game
-                                    .loot_boxes
-                                    .iter()
-                                    .filter(|loot| is_weapon(*loot))
-                                    .filter_map(|loot| distance_map.get(&tile_pos(loot.position))
-                                        .and_then(|map| map.get(&(last_mov.pos2, last_mov.vertical_state2))))
-                                    .min()
-                                    .unwrap_or(&very_long_dist);
-
-                                damage_cost + min_ticks as f64
-                            }
-                        } else if need_health {
-                            if path.iter()
-                                .filter_map(|mov| loot_map.get(&mov.pos2))
-                                .any(|loot| is_health(loot)) {
-                                // встретили по дороге
-                                damage_cost
-                            } else {
-                                // определяем расстояние ближайшего предмета
-                                let min_ticks = *game
-                                    .loot_boxes
-                                    .iter()
-                                    .filter(|loot| is_health(*loot))
-                                    .filter_map(|loot| distance_map.get(&tile_pos(loot.position))
-                                        .and_then(|map| map.get(&(last_mov.pos2, last_mov.vertical_state2))))
-                                    .min()
-                                    .unwrap_or(&very_long_dist);
-
-                                damage_cost + min_ticks as f64
-                            }
-                        } else if false /*USE_MINE_SUICIDE*/ && unit.mines < 2 && game.loot_boxes.iter().filter(|loot| is_mine(loot)).count() >= 2 - unit.mines as usize {
-                            if path.iter()
-                                .filter_map(|mov| loot_map.get(&mov.pos2))
-                                .any(|loot| is_mine(loot)) {
-                                // встретили по дороге
-                                damage_cost
-                            } else {
-                                // определяем расстояние ближайшего предмета
-                                let min_ticks = *game
-                                    .loot_boxes
-                                    .iter()
-                                    .filter(|loot| is_mine(*loot))
-                                    .filter_map(|loot| distance_map.get(&tile_pos(loot.position))
-                                        .and_then(|map| map.get(&(last_mov.pos2, last_mov.vertical_state2))))
-                                    .min()
-                                    .unwrap_or(&very_long_dist);
-
-                                damage_cost + min_ticks as f64
-                            }
-                        } else {
-                            let min_dist_to_enemy = path.iter()
-                                .filter_map(|mov| enemy_distance_map.iter().filter_map(|(id, map)| {
-                                    let enemy = game.units
-                                        .iter()
-                                        .find(|unit2| unit2.id == *id)
-                                        .unwrap();
-                                    let fire_timer_ticks = enemy.weapon.as_ref().and_then(|weapon| weapon.fire_timer).unwrap_or(0.0) * game.properties.ticks_per_second;
-                                    map.get(&(mov.pos2, mov.vertical_state2)).map(|dist| dist + fire_timer_ticks as i32)
-                                }).min())
-                                .min()
-                                .unwrap_or(very_long_dist) as f64;
-
-                            let min_dist = 50.0;
-                            let cost = damage_cost + (min_dist_to_enemy - min_dist).abs() * 10.0;
-                            cost
-                        };
 
 //                println!("i {} cost {} {}", i, cost, if cost < best_cost {"***"} else {""});
 
@@ -439,11 +647,51 @@ impl MyStrategy {
                         best_old = cost;
                     }
                 }
+                if USE_MCTS {
+                    let mut shot_value = MyStrategy::shot_value_model(unit, paths, game);
+                    let fire_state = unit.weapon.as_ref()
+                        .map(|weapon| FireState {
+                            ready_tick: (weapon.fire_timer.unwrap_or(0.0) * game.properties.ticks_per_second).ceil() as i32,
+                            magazine: if weapon.magazine > 0 { weapon.magazine } else { weapon.params.magazine_size },
+                        })
+                        .unwrap_or(FireState { ready_tick: 0, magazine: 0 });
+                    let mut tree = unit_data.mcts_tree.take()
+                        .and_then(|tree| tree.reroot(pos, vertical_state, fire_state))
+                        .unwrap_or_else(|| Tree::new(pos, vertical_state, fire_state));
+                    let reused_visits = tree.root_visits();
+                    let planner = Planner {
+                        paths,
+                        weapon: unit.weapon.as_ref().map(|weapon| WeaponModel::new(weapon, game.properties.ticks_per_second)),
+                        horizon: max_ticks,
+                        iterations: MCTS_ITERATIONS,
+                        time_limit_ms: if self.time_limits { Some(MyStrategy::search_time_limit(self.total_time, game)) } else { None },
+                    };
+                    let iterations = planner.search(&mut tree, &mut unit_data.rand,
+                                                    &|movs, rand| MyStrategy::sample_move(movs, simple_target_distance_map, simple_target_dist, rand),
+                                                    &mut path_cost, &mut shot_value);
+                    // план поиска берем вместо быстрого пути: поиск оптимизирует ценность с выстрелами, а не одну стоимость пути;
+                    // быстрый путь остается, только если план упирается в другого игрока
+                    if let Some(plan) = planner.best_plan(&tree) {
+                        let cost = path_cost(&plan.path);
+                        println!("mcts {} iterations, reused {} visits, value {} cost {:?} fire {}",
+                                 iterations, reused_visits, plan.value, cost, plan.fire);
+                        if let Some(cost) = cost.filter(|_| plan.path.len() > 1) {
+                            best_cost = cost;
+                            unit_data.hold_fire = if plan.fire { None } else { Some(plan.path[1].clone()) };
+                            unit_data.path = plan.path;
+                            unit_data.path_start_tick = game.current_tick;
+                        }
+                    }
+                    unit_data.mcts_tree = Some(tree);
+                }
                 println!("used old {}, best old {}", best_used_old, best_old);
+                if damage_calls > 0 {
+                    println!("damage cache {} moves of {} calls", damage_cache.len(), damage_calls);
+                }
 
                 if !unit_data.path.is_empty() {
                     unit_data.move_ = Some(unit_data.path[1].clone());
-                    move_action = match (unit_data.move_.as_ref().unwrap().control)(unit.position, MyStrategy::get_vertical_state(unit, game)) {
+                    move_action = match smooth_control(&unit_data.path[1], unit_data.path.get(2), unit.position, MyStrategy::get_vertical_state(unit, game)) {
                         ControlResult::TargetReached => unreachable!(),
                         ControlResult::Recover => unreachable!(),
                         ControlResult::MoveAction(move_action2) => Some(move_action2),
@@ -456,6 +704,16 @@ impl MyStrategy {
             }
         }
 
+        // пуля или мина попадет в нас в ближайшие тики - уворачиваемся, потом возвращаемся на граф через recover
+        if USE_DODGE && move_action.as_ref().map(|mov| mov.typ != MoveType::MineSuicide).unwrap_or(true) {
+            if let Some(dodge_action) = MyStrategy::choose_dodge(unit, move_action.as_ref(), &bullets, game) {
+                println!("dodge {:?}", dodge_action);
+                move_action = Some(dodge_action);
+                unit_data.path.clear();
+                unit_data.move_ = Some(MyStrategy::recovery_move(unit, paths, game).unwrap_or_else(get_recover_move));
+            }
+        }
+
         // все достижимые точки
 //        MyStrategy::draw_all_movements(unit, game, debug);
         // первые движения
@@ -481,9 +739,12 @@ impl MyStrategy {
             }
         }
 
+        if DRAW_DANGER_MAP {
+            danger_map.iter().for_each(|danger_map| danger_map.draw(&game.properties, debug));
+        }
+
         // показ попаданий в нас
         {
-            let bullets = Bullets::new(game);
             for bullet in &bullets.bullets {
                 debug.draw(model::CustomData::Line {
                     p1: Vec2F32::from64(bullet.0.position),
@@ -498,80 +759,69 @@ impl MyStrategy {
                 });
             }
 
+            // мины: радиус срабатывания готовых, радиус взрыва сработавших
+            for (mine, (explosion_tick, _)) in bullets.mines.iter().zip(&bullets.mine_ticks) {
+                let center = mine_center(mine.position, &game.properties);
+                if explosion_tick.is_some() {
+                    MyStrategy::draw_square(center, mine.explosion_params.radius, ColorF32 {
+                        r: 1.0,
+                        g: 0.0,
+                        b: 0.0,
+                        a: 0.3,
+                    }, 0.05, debug);
+                } else {
+                    MyStrategy::draw_square(center, mine.trigger_radius, ColorF32 {
+                        r: 1.0,
+                        g: 0.5,
+                        b: 0.0,
+                        a: 0.3,
+                    }, 0.05, debug);
+                }
+            }
+
             let mut bullets_state = BulletsState::new();
+            let positions = MyStrategy::predict_positions(unit, unit_data, 60, game);
 
             for tick in 0..60 {
                 if !bullets.need_test(&bullets_state) {
                     break;
                 }
-                let path_tick = tick + (game.current_tick - unit_data.path_start_tick);
-                let (position1, position2) = MyStrategy::get_unit_position_at_tick(&unit, &unit_data.path, path_tick);
+                let unit_positions = position_at(&positions, tick);
 
-                let micro_ticks = 100;
-                for micro_tick in 0..micro_ticks {
-                    let t = micro_tick as f64 / micro_ticks as f64;
-                    let position = position1.add(position2.sub(position1).mul(t));
+                let (bullet_hits, explosion_hits, new_bullets_state) =
+                    bullets.test(unit_positions, unit.id, tick as f64, tick as f64 + 1.0, &bullets_state, &game.properties);
+                bullets_state = new_bullets_state;
 
-                    let (bullet_hits, explosion_hits, new_bullets_state) =
-                        (&bullets).test(position, unit.id, tick as f64 + t, &bullets_state, &game.properties);
-                    bullets_state = new_bullets_state;
+                if bullet_hits.is_some() || explosion_hits.is_some() {
+//                    println!("tick {} {:?}", tick, explosion_hits);
 
-                    if bullet_hits.is_some() || explosion_hits.is_some() {
-//                        println!("tick {} mtick {} {:?}", tick, micro_tick, explosion_hits);
-
-                        let unit_color = ColorF32 {
-                            r: 1.0,
-                            g: 1.0,
-                            b: 0.0,
-                            a: 0.5,
-                        };
-                        let hit_color = ColorF32 {
-                            r: 1.0,
-                            g: 0.0,
-                            b: 0.8,
-                            a: 1.0,
-                        };
+                    let unit_color = ColorF32 {
+                        r: 1.0,
+                        g: 1.0,
+                        b: 0.0,
+                        a: 0.5,
+                    };
+                    let hit_color = ColorF32 {
+                        r: 1.0,
+                        g: 0.0,
+                        b: 0.8,
+                        a: 1.0,
+                    };
 
-                        MyStrategy::draw_unit(position, unit_color, 0.1, &game.properties, debug);
-
-                        for explosion in explosion_hits.unwrap_or_default() {
-                            let bullet_position = explosion.0;
-                            let radius = explosion.2;
-                            debug.draw(model::CustomData::Line {
-                                p1: Vec2F32 { x: (bullet_position.x - radius) as f32, y: (bullet_position.y - radius) as f32 },
-                                p2: Vec2F32 { x: (bullet_position.x - radius) as f32, y: (bullet_position.y + radius) as f32 },
-                                color: hit_color.clone(),
-                                width: 0.1,
-                            });
-                            debug.draw(model::CustomData::Line {
-                                p1: Vec2F32 { x: (bullet_position.x - radius) as f32, y: (bullet_position.y + radius) as f32 },
-                                p2: Vec2F32 { x: (bullet_position.x + radius) as f32, y: (bullet_position.y + radius) as f32 },
-                                color: hit_color.clone(),
-                                width: 0.1,
-                            });
-                            debug.draw(model::CustomData::Line {
-                                p1: Vec2F32 { x: (bullet_position.x + radius) as f32, y: (bullet_position.y + radius) as f32 },
-                                p2: Vec2F32 { x: (bullet_position.x + radius) as f32, y: (bullet_position.y - radius) as f32 },
-                                color: hit_color.clone(),
-                                width: 0.1,
-                            });
-                            debug.draw(model::CustomData::Line {
-                                p1: Vec2F32 { x: (bullet_position.x + radius) as f32, y: (bullet_position.y - radius) as f32 },
-                                p2: Vec2F32 { x: (bullet_position.x - radius) as f32, y: (bullet_position.y - radius) as f32 },
-                                color: hit_color.clone(),
-                                width: 0.1,
-                            });
-                        }
+                    for explosion in explosion_hits.unwrap_or_default() {
+                        MyStrategy::draw_unit(explosion.1, unit_color.clone(), 0.1, &game.properties, debug);
+                        MyStrategy::draw_square(explosion.0, explosion.3, hit_color.clone(), 0.1, debug);
+                    }
 
-                        for bullet_hit in bullet_hits.unwrap_or_default() {
-                            let bullet_position = bullet_hit.0;
-                            let rect_size = 0.3;
-                            debug.draw(model::CustomData::Rect {
-                                pos: Vec2F32::from64(bullet_position.sub(Vec2F64 { x: rect_size / 2.0, y: rect_size / 2.0 })),
-                                size: Vec2F32 { x: rect_size as f32, y: rect_size as f32 },
-                                color: hit_color.clone(),
-                            });
-                        }
+                    for bullet_hit in bullet_hits.unwrap_or_default() {
+                        let bullet_position = bullet_hit.0;
+                        let rect_size = 0.3;
+                        MyStrategy::draw_unit(bullet_hit.1, unit_color.clone(), 0.1, &game.properties, debug);
+                        debug.draw(model::CustomData::Rect {
+                            pos: Vec2F32::from64(bullet_position.sub(Vec2F64 { x: rect_size / 2.0, y: rect_size / 2.0 })),
+                            size: Vec2F32 { x: rect_size as f32, y: rect_size as f32 },
+                            color: hit_color.clone(),
+                        });
                     }
                 }
             }
@@ -580,6 +830,8 @@ impl MyStrategy {
         println!("pos {:?} action {:?}", unit.position, move_action);
 
         let mut aim = Vec2F64 { x: 0.0, y: 0.0 };
+        // вероятность попадания пулей в ближайшего противника при выбранном прицеле
+        let mut aim_hit_chance = 0.0;
         if let Some(weapon) = &unit.weapon {
             let nearest_enemy = game
                 .units
@@ -601,56 +853,34 @@ impl MyStrategy {
                     a: 0.5,
                 }, 0.1, &game.properties, debug);
 
-                if let Some(last_angle) = weapon.last_angle {
-                    let p00 = enemy_position.add(Vec2F64 { x: -enemy.size.x / 2.0, y: 0.0 });
-                    let p10 = enemy_position.add(Vec2F64 { x: enemy.size.x / 2.0, y: 0.0 });
-                    let p01 = enemy_position.add(Vec2F64 { x: -enemy.size.x / 2.0, y: enemy.size.y });
-                    let p11 = enemy_position.add(Vec2F64 { x: enemy.size.x / 2.0, y: enemy.size.y });
-
-                    let angle_to_center = (enemy_position.y - unit.position.y).atan2(enemy_position.x - unit.position.x);
-                    let unit_center = unit.position.add(Vec2F64 { x: 0.0, y: game.properties.unit_size.y / 2.0 });
-                    let enemy_spread = [p00, p10, p01, p11].iter()
-                        .map(|p| delta_angle(angle_to_center, (p.y - unit_center.y).atan2(p.x - unit_center.x)).abs())
-                        .max_by_key(|angle| NonNan::new(*angle))
-                        .unwrap();
-                    let delta = delta_angle(last_angle, angle_to_center);
-
-//                    println!("last_angle {} angle_to_center {} delta {} enemy_spread {} weapon.spread {}", last_angle, angle_to_center, delta, enemy_spread, weapon.spread);
-
-                    //проверим, нужно ли менять прицел, или противник и так в угле поражения?
-                    if enemy_spread <= weapon.spread {
-                        let miss_angle = delta.abs() + enemy_spread - weapon.spread;
-                        if miss_angle > 0.0 {
-                            // подвинем на минимальный необходимый угол
-                            let new_angle = last_angle + if delta > 0.0 { miss_angle } else { -miss_angle };
-//                            println!("last_angle {} new_angle {} rotate {}", last_angle, new_angle, delta_angle(last_angle, new_angle));
-                            aim = Vec2F64 {
-                                x: new_angle.cos(),
-                                y: new_angle.sin(),
-                            };
-                        } else {
-                            aim = Vec2F64 {
-                                x: last_angle.cos(),
-                                y: last_angle.sin(),
-                            };
-                        }
-                    } else {
-                        // прицел точнее, чем размер противника, просто целимся в противника
-                        aim = Vec2F64 {
-                            x: enemy_position.x - unit.position.x,
-                            y: enemy_position.y - unit.position.y,
-                        };
-                    }
-                } else {
-                    // просто целимся в противника
-                    aim = Vec2F64 {
-                        x: enemy_position.x - unit.position.x,
-                        y: enemy_position.y - unit.position.y,
-                    };
-                }
+                let p00 = enemy_position.add(Vec2F64 { x: -enemy.size.x / 2.0, y: 0.0 });
+                let p10 = enemy_position.add(Vec2F64 { x: enemy.size.x / 2.0, y: 0.0 });
+                let p01 = enemy_position.add(Vec2F64 { x: -enemy.size.x / 2.0, y: enemy.size.y });
+                let p11 = enemy_position.add(Vec2F64 { x: enemy.size.x / 2.0, y: enemy.size.y });
+
+                let angle_to_center = (enemy_position.y - unit.position.y).atan2(enemy_position.x - unit.position.x);
+                let unit_center = unit.position.add(Vec2F64 { x: 0.0, y: game.properties.unit_size.y / 2.0 });
+                let enemy_spread = [p00, p10, p01, p11].iter()
+                    .map(|p| delta_angle(angle_to_center, (p.y - unit_center.y).atan2(p.x - unit_center.x)).abs())
+                    .max_by_key(|angle| NonNan::new(*angle))
+                    .unwrap();
+
+                // поворот прицела раскачивает разброс, выбираем направление с лучшим шансом попасть к моменту выстрела
+                let fire_ticks = weapon.fire_timer.unwrap_or(0.0) * game.properties.ticks_per_second;
+                let choice = choose_aim(weapon, angle_to_center, enemy_spread, fire_ticks, game.properties.ticks_per_second);
+//                println!("angle_to_center {} enemy_spread {} weapon.spread {} aim {:?}", angle_to_center, enemy_spread, weapon.spread, choice);
+                aim = Vec2F64 {
+                    x: choice.angle.cos(),
+                    y: choice.angle.sin(),
+                };
+                aim_hit_chance = choice.hit_chance;
             }
         }
 
+        let at_mine_target = unit.on_ground && unit.mines > 0 && unit_data.mine_plan.as_ref()
+            .map(|plan| plan.kind == MinePlanKind::Plant && plan.target == tile_pos(unit.position) && can_plant_mine(plan.target, &game.level))
+            .unwrap_or(false);
+
         let mut can_suicide = false;
         if suicide_damage > 0 && unit_data.move_.as_ref().is_some() &&
             unit_data.move_.as_ref().unwrap().typ != MoveType::MineSuicide &&
@@ -658,14 +888,19 @@ impl MyStrategy {
             // если в конце текущего хода можно будет применить мины, то не стреляем
             let pos2 = unit_data.move_.as_ref().unwrap().pos2;
             let target = to_unit_position(pos2);
-            let ground = MyStrategy::can_plant_mine(pos2, &game.level);
-            if ground && MyStrategy::suicide_is_effective(target, unit.player_id, suicide_damage, &game) {
+            let ground = can_plant_mine(pos2, &game.level);
+            if ground && MyStrategy::suicide_is_effective(target, unit, game) {
                 println!("can_suicide");
                 can_suicide = true;
             }
         }
 
-        let mut shoot = !can_suicide && self.shoot(unit, aim, game, debug);
+        // решение поиска не стрелять действует до конца хода, для которого оно принято
+        if unit_data.hold_fire != unit_data.move_ {
+            unit_data.hold_fire = None;
+        }
+        let hold_fire = USE_MCTS && unit_data.hold_fire.is_some();
+        let mut shoot = !can_suicide && !hold_fire && self.shoot(unit, aim, aim_hit_chance, game, debug);
         let mut plant_mine = false;
 
         if move_action.is_some() && move_action.as_ref().unwrap().typ == MoveType::MineSuicide {
@@ -679,6 +914,14 @@ impl MyStrategy {
             }
         }
 
+        // дошли до места для мины
+        if !plant_mine && at_mine_target {
+            println!("plant mine {:?}", tile_pos(unit.position));
+            plant_mine = true;
+        }
+
+        let swap_weapon = should_swap_weapon(unit, game);
+
         self.total_time += now.elapsed().as_millis();
 
         // запоминаем последнее состояние противника
@@ -694,33 +937,14 @@ impl MyStrategy {
             jump_down: move_action.as_ref().map(|mov| mov.jump_down).unwrap_or(false),
             aim: aim.mul(2.0), // защита от 0.5
             shoot,
-            reload: false,
-            swap_weapon: false,
+            reload,
+            swap_weapon,
             plant_mine,
         }
     }
 
-    fn can_plant_mine(pos: TilePos, level: &Level) -> bool {
-        if level.tiles[pos.0 as usize][pos.1 as usize] == Tile::Ladder || level.tiles[pos.0 as usize][(pos.1 + 1) as usize] == Tile::Ladder {
-            return false;
-        }
-        match level.tiles[pos.0 as usize][(pos.1 - 1) as usize] {
-            Tile::Wall => true,
-            Tile::Platform => true,
-            _ => false,
-        }
-    }
-
     fn get_vertical_state(unit: &Unit, game: &Game) -> VerticalState {
-        if !unit.jump_state.can_jump || unit.jump_state.max_time == game.properties.unit_jump_time {
-            VerticalState::Default
-        } else {
-            if unit.jump_state.speed == game.properties.jump_pad_jump_speed {
-                VerticalState::PadJump((unit.jump_state.speed * unit.jump_state.max_time).floor() as usize)
-            } else {
-                VerticalState::Jump((unit.jump_state.speed * unit.jump_state.max_time).floor() as usize)
-            }
-        }
+        vertical_state(&unit.jump_state, &game.properties)
     }
 
     fn draw_first_movements(unit: &Unit, game: &Game, debug: &mut crate::Debug) {
@@ -759,7 +983,7 @@ impl MyStrategy {
         let mut queue = VecDeque::new();
         let pos = tile_pos(unit.position.clone());
         let vertical_state = MyStrategy::get_vertical_state(unit, game);
-        queue.push_back(MyStrategy::make_start_node(pos, vertical_state));
+        queue.push_back(make_start_node(pos, vertical_state));
         while !queue.is_empty() {
             let mov = queue.pop_front().unwrap();
             if !known.insert((mov.pos2, mov.vertical_state2)) {
@@ -802,135 +1026,79 @@ impl MyStrategy {
         });
     }
 
-    fn make_start_node(pos: TilePos, vertical_state: VerticalState) -> Move {
-        Move {
-            typ: MoveType::Start,
-            pos1: pos,
-            vertical_state1: vertical_state,
-            pos2: pos,
-            vertical_state2: vertical_state,
-            ticks: 0,
-            control: Rc::new(|_, _| ControlResult::TargetReached),
-        }
-    }
-
-    /// посчитать, где пуля столкнется со стеной/границей мира
-    fn bullet_end(position: Vec2F64, aim: Vec2F64, weapon_type: &WeaponType, level: &Level, properties: &Properties) -> (Vec2F64, f64) {
-        let weapon_params = &properties.weapon_params[weapon_type];
-        let speed = {
-            let aim_length = ((aim.x).powi(2) + (aim.y).powi(2)).sqrt();
-            Vec2F64 { x: aim.x * weapon_params.bullet.speed / aim_length, y: aim.y * weapon_params.bullet.speed / aim_length }
-        };
-        let half_bullet_size = weapon_params.bullet.size / 2.0;
-        let mut position2 = position;
-        let time_step = 1.0 / properties.ticks_per_second / 100.0;// 100 microticks
-        loop {
-            position2 = position2.add(speed.mul(time_step));
-            if MyStrategy::wall_collision(Vec2F64 { x: position2.x - half_bullet_size, y: position2.y - half_bullet_size }, &level) ||
-                MyStrategy::wall_collision(Vec2F64 { x: position2.x + half_bullet_size, y: position2.y - half_bullet_size }, &level) ||
-                MyStrategy::wall_collision(Vec2F64 { x: position2.x - half_bullet_size, y: position2.y + half_bullet_size }, &level) ||
-                MyStrategy::wall_collision(Vec2F64 { x: position2.x + half_bullet_size, y: position2.y + half_bullet_size }, &level) {
-                let len = distance_sqr(position, position2).sqrt();
-                let tick = len / weapon_params.bullet.speed * properties.ticks_per_second;
-                return (position2, tick);
-            }
-        }
-    }
-
-    fn wall_collision(pos: Vec2F64, level: &Level) -> bool {
-        if pos.x <= 0.0 || pos.x >= level.width() as f64 || pos.y <= 0.0 || pos.y >= level.height() as f64 {
-            return true;
-        }
-        match level.tiles[pos.x as usize][pos.y as usize] {
-            Tile::Wall => true,
-            _ => false
-        }
-    }
-
-    fn shoot(&self, unit: &Unit, aim: Vec2F64, game: &Game, _debug: &mut crate::Debug) -> bool {
+    /// стрелять ли сейчас: оружие готово, есть шанс попасть и ожидаемый урон противнику оправдывает риск задеть своих
+    fn shoot(&self, unit: &Unit, aim: Vec2F64, hit_chance: f64, game: &Game, _debug: &mut crate::Debug) -> bool {
         if let Some(weapon) = &unit.weapon {
             if weapon.fire_timer.is_none() {
                 // стрелять только в случае, если есть заметный шанс попасть (с учетом explosion)
+                if weapon.params.explosion.is_none() && hit_chance < MIN_HIT_CHANCE {
+                    println!("hit chance {} too low", hit_chance);
+                    return false;
+                }
 
                 // учесть разброс от aim
                 let angle = aim.y.atan2(aim.x);
-                let spread = (weapon.spread +
-                    if weapon.last_angle.is_some() { delta_angle(weapon.last_angle.unwrap(), angle).abs() } else { 0.0 })
-                    .min(weapon.params.max_spread);
+                let spread = spread_after(weapon, weapon.last_angle.map(|last_angle| delta_angle(last_angle, angle)).unwrap_or(0.0),
+                                          0.0, game.properties.ticks_per_second);
 
                 let bullet_from = Vec2F64 { x: unit.position.x, y: unit.position.y + unit.size.y / 2.0 };
-                let parts = 10; // сколько направлений проверяем
-                let mut damage_myself = 0.0;
-                let mut damage_enemy = 0.0;
-                for i in 0..parts {
-                    let angle = -spread + i as f64 * (spread * 2.0 / (parts - 1) as f64);
-                    let (mut bullet_end, mut bullet_end_tick) = MyStrategy::bullet_end(bullet_from.clone(), aim.rotate(angle), &weapon.typ, &game.level, &game.properties);
-
-                    // проверить, не попадем ли в какого-то игрока (кроме стреляющего)
-                    let mut unit_hit_player: Option<i32> = None;
-                    let mut unit_hit_id: Option<i32> = None;
-                    let mut last_bullet_pos = bullet_from;
-                    for tick in 0..=bullet_end_tick.floor() as i32 {
-                        let bullet_at_tick = bullet_from.add(bullet_end.sub(bullet_from).mul(if bullet_end_tick < 1e-6 { 0.0 } else { tick as f64 / bullet_end_tick }));
-
-                        for unit2 in &game.units {
-                            if unit2.id == unit.id {
-                                continue;
-                            }
 
-                            let bullet_radius = weapon.params.bullet.size / 2.0;
-
-                            let unit2_position =
-                                if unit2.player_id == unit.player_id {
-                                    let unit_data = if self.unit1_data.id == unit2.id { &self.unit1_data } else { &self.unit2_data };
-                                    let path_tick = tick + (game.current_tick - unit_data.path_start_tick);
-                                    let path = /*&unit_data.path;*/ if unit_data.path.is_empty() { &unit_data.path } else { &unit_data.path[0..1] };
-                                    MyStrategy::get_unit_position_at_tick(&unit2, path, path_tick).0
-                                } else {
-                                    unit2.position.clone()
-                                };
-
-                            let p00 = unit2_position.add(Vec2F64 { x: -unit2.size.x / 2.0 - bullet_radius, y: 0.0 - bullet_radius });
-                            let p10 = unit2_position.add(Vec2F64 { x: unit2.size.x / 2.0 + bullet_radius, y: 0.0 - bullet_radius });
-                            let p01 = unit2_position.add(Vec2F64 { x: -unit2.size.x / 2.0 - bullet_radius, y: unit2.size.y + bullet_radius });
-                            let p11 = unit2_position.add(Vec2F64 { x: unit2.size.x / 2.0 + bullet_radius, y: unit2.size.y + bullet_radius });
-
-//                            if game.current_tick == 100 {
-//                                println!("tick {}, bullet_at_tick {:?}, p {:?} {:?}", tick, bullet_at_tick, p00, p11);
-//                            }
-
-                            for segment in &[(p00, p10), (p10, p11), (p11, p01), (p01, p00)] {
-                                if let Some(pos) = segments_intersection(last_bullet_pos, bullet_at_tick, segment.0, segment.1) {
-                                    let dist_pos_sqr = distance_sqr(bullet_from, pos);
-                                    let dist_end_sqr = distance_sqr(bullet_from, bullet_at_tick);
-//                                    if game.current_tick == 100 {
-//                                        println!("pos {:?} dp {} de {} et {}", pos, dist_pos_sqr, dist_end_sqr, dist_pos_sqr.sqrt() / dist_end_sqr.sqrt());
-//                                    }
-                                    if dist_pos_sqr <= dist_end_sqr {
-                                        bullet_end = pos;
-                                        bullet_end_tick *= dist_pos_sqr.sqrt() / dist_end_sqr.sqrt();
-                                        unit_hit_player = Some(unit2.player_id);
-                                        unit_hit_id = Some(unit2.id);
-
-                                        MyStrategy::draw_unit(unit2_position, ColorF32 {
-                                            r: 1.0,
-                                            g: 1.0,
-                                            b: 0.5,
-                                            a: 0.5,
-                                        }, 0.05, &game.properties, _debug);
-                                    }
-                                }
-                            }
-                        }
+                // если ни одного противника не видно, то пулей не попасть (взрыв может задеть и из-за угла)
+                if weapon.params.explosion.is_none() && !game.units.iter()
+                    .filter(|unit2| unit2.player_id != unit.player_id)
+                    .any(|unit2| Visibility::unit_visible(bullet_from, unit2.position, unit2.size, weapon.params.bullet.size, &game.level)) {
+                    return false;
+                }
 
-                        if unit_hit_player.is_some() {
-                            break;
-                        }
+                let estimate = self.estimate_shot(unit, weapon, aim, spread, game, _debug);
+                println!("shot enemy {} +- {} ally {} +- {}", estimate.enemy_damage, estimate.enemy_variance().sqrt(),
+                         estimate.ally_damage, estimate.ally_variance().sqrt());
+                estimate.is_worth(SHOT_RISK)
+            } else {
+                false
+            }
+        } else {
+            false
+        }
+    }
 
-                        last_bullet_pos = bullet_at_tick;
-                    }
+    /// Ожидаемый урон от выстрела: направление пули равномерно в [-spread, spread] (интегрируем по средним точкам),
+    /// положения противников к моменту подлета пули - по распределению достижимых клеток,
+    /// сценарии противников независимы, их вероятности перемножаются.
+    fn estimate_shot(&self, unit: &Unit, weapon: &Weapon, aim: Vec2F64, spread: f64, game: &Game, debug: &mut crate::Debug) -> ShotEstimate {
+        let bullet_from = Vec2F64 { x: unit.position.x, y: unit.position.y + unit.size.y / 2.0 };
+        // сценарии: положения игроков по тикам (id, положения) и вероятность;
+        // свои идут по своим планам, противник стоит в одной из вероятных точек
+        let teammates = game.units.iter()
+            .filter(|unit2| unit2.player_id == unit.player_id && unit2.id != unit.id)
+            .map(|unit2| {
+                let unit_data = if self.unit1_data.id == unit2.id { &self.unit1_data } else { &self.unit2_data };
+                (unit2.id, MyStrategy::predict_positions(unit2, unit_data, SHOT_PREDICTION_TICKS, game))
+            })
+            .collect::<Vec<(i32, Vec<Vec2F64>)>>();
+        let mut scenarios = vec![(teammates, 1.0)];
+        for enemy in game.units.iter().filter(|unit2| unit2.player_id != unit.player_id) {
+            let ticks_to_hit = distance_sqr(bullet_from, enemy.position).sqrt() / weapon.params.bullet.speed * game.properties.ticks_per_second;
+            let positions = MyStrategy::estimate_enemy_positions(enemy, ticks_to_hit, &self.paths, game);
+            scenarios = scenarios.iter()
+                .flat_map(|(scenario, probability)| positions.iter().map(move |(position, probability2)| {
+                    let mut scenario2 = scenario.clone();
+                    scenario2.push((enemy.id, vec![*position]));
+                    (scenario2, probability * probability2)
+                }))
+                .collect();
+        }
 
-                    _debug.draw(model::CustomData::Line {
+        let mut estimate = ShotEstimate::default();
+        for i in 0..SHOT_SAMPLES {
+            let angle = -spread + (i as f64 + 0.5) * (spread * 2.0 / SHOT_SAMPLES as f64);
+            let direction = aim.rotate(angle);
+            let angle_probability = 1.0 / SHOT_SAMPLES as f64;
+            for (index, (positions, probability)) in scenarios.iter().enumerate() {
+                let (enemy_damage, ally_damage, bullet_end) = MyStrategy::shot_outcome(unit, weapon, bullet_from, direction, positions, game);
+                estimate.add(enemy_damage, ally_damage, angle_probability * probability);
+                if index == 0 {
+                    debug.draw(model::CustomData::Line {
                         p1: Vec2F32::from64(bullet_from),
                         p2: Vec2F32::from64(bullet_end),
                         color: ColorF32 {
@@ -941,177 +1109,241 @@ impl MyStrategy {
                         },
                         width: 0.05,
                     });
+                }
+            }
+        }
+        estimate
+    }
 
-                    // корректируем оценку ущерба врагу в зависимости от расстояния (чтобы балансировать с ущербом себе)
-                    let enemy_damage_coef = 1.0 / (bullet_end_tick * game.properties.unit_max_horizontal_speed / game.properties.ticks_per_second).max(1.0);
+    /// один выстрел в направлении direction при заданных положениях игроков по тикам (кого нет - стоит на месте):
+    /// урон противникам, урон своим (и себе), конец полета пули
+    fn shot_outcome(unit: &Unit, weapon: &Weapon, bullet_from: Vec2F64, direction: Vec2F64, positions: &[(i32, Vec<Vec2F64>)],
+                    game: &Game) -> (f64, f64, Vec2F64) {
+        let (mut bullet_end, mut bullet_end_tick) = bullet_end(bullet_from, direction, &weapon.typ, &game.level, &game.properties);
+        let unit_position_at = |unit2: &Unit, tick: i32| positions.iter()
+            .find(|(id, _)| *id == unit2.id)
+            .map(|(_, positions2)| position_at(positions2, tick))
+            .unwrap_or((unit2.position, unit2.position));
+
+        // проверить, не попадем ли в какого-то игрока (кроме стреляющего)
+        let mut unit_hit_player: Option<i32> = None;
+        let bullet_velocity = {
+            let length = (direction.x.powi(2) + direction.y.powi(2)).sqrt();
+            direction.mul(weapon.params.bullet.speed / length / game.properties.ticks_per_second)
+        };
+        let bullet_half_size = Vec2F64 { x: weapon.params.bullet.size / 2.0, y: weapon.params.bullet.size / 2.0 };
+        // по тикам, внутри тика все движутся равномерно
+        for tick in 0..=bullet_end_tick.floor() as i32 {
+            let bullet_at_tick = bullet_from.add(bullet_velocity.mul(tick as f64));
+            let max_time = (bullet_end_tick - tick as f64).min(1.0);
+            let mut first_hit: Option<(f64, &Unit)> = None;
 
-                    let mut damage_per_unit = FnvHashMap::default();
+            for unit2 in &game.units {
+                if unit2.id == unit.id {
+                    continue;
+                }
 
-                    // оценка ущерба от пули
-                    if let Some(unit_hit_player) = unit_hit_player {
-                        let damage = weapon.params.bullet.damage;
-                        if unit_hit_player != unit.player_id {
-                            println!("tick {} enemy_damage_coef {}", bullet_end_tick, enemy_damage_coef);
-                            let damage2 = damage as f64 * enemy_damage_coef;
-                            damage_enemy += damage2;
-                            *damage_per_unit.entry(unit_hit_id.unwrap()).or_insert(0.0) += damage2;
-                        } else {
-                            damage_myself += damage as f64;
-                            *damage_per_unit.entry(unit_hit_id.unwrap()).or_insert(0.0) += damage as f64;
-                        }
-                    }
+                let (unit2_position1, unit2_position2) = unit_position_at(unit2, tick);
 
-                    // оценка ущерба от взрыва
-                    if weapon.params.explosion.is_some() {
-                        let explosion_radius = weapon.params.explosion.as_ref().unwrap().radius;
-                        let damage = weapon.params.explosion.as_ref().unwrap().damage;
-                        for unit2 in &game.units {
-                            let unit_position =
-                                if unit2.player_id == unit.player_id {
-                                    let unit_data = if self.unit1_data.id == unit2.id { &self.unit1_data } else { &self.unit2_data };
-                                    let path_tick = bullet_end_tick.floor() as i32 + (game.current_tick - unit_data.path_start_tick);
-                                    let t = bullet_end_tick % 1.0;
-                                    let (position1, position2) = MyStrategy::get_unit_position_at_tick(&unit2, &unit_data.path, path_tick);
-                                    position1.add(position2.sub(position1).mul(t))
-                                } else {
-                                    unit2.position.clone()
-                                };
-                            if MyStrategy::damage_unit_by_explosion(unit_position, bullet_end, explosion_radius, unit.size.y) {
-                                if unit2.player_id != unit.player_id {
-                                    println!("tick {} enemy_damage_coef {}", bullet_end_tick, enemy_damage_coef);
-                                    let damage2 = damage as f64 * enemy_damage_coef;
-                                    damage_enemy += damage2;
-                                    *damage_per_unit.entry(unit2.id).or_insert(0.0) += damage2;
-                                } else {
-                                    MyStrategy::draw_unit(unit_position, ColorF32 {
-                                        r: 1.0,
-                                        g: 0.5,
-                                        b: 0.0,
-                                        a: 0.5,
-                                    }, 0.1, &game.properties, _debug);
-                                    damage_myself += damage as f64;
-                                    *damage_per_unit.entry(unit2.id).or_insert(0.0) += damage as f64;
-                                }
-                            }
-                        }
+                let unit2_half_size = unit2.size.mul(0.5);
+                let hit_time = swept_aabb(bullet_at_tick, bullet_half_size, bullet_velocity,
+                                          unit2_position1.add(Vec2F64 { x: 0.0, y: unit2_half_size.y }), unit2_half_size,
+                                          unit2_position2.sub(unit2_position1), max_time);
+                if let Some(hit_time) = hit_time {
+                    let first = match &first_hit {
+                        Some(hit) => hit_time < hit.0,
+                        None => true,
+                    };
+                    if first {
+                        first_hit = Some((hit_time, unit2));
                     }
-
-//                    // добавим скор за смерть
-//                    let dead_cost = 100.0;
-//                    for unit2 in &game.units {
-//                        let unit_damage = *damage_per_unit.get(&unit2.id).unwrap_or(&0.0);
-//                        if (unit2.health as f64) < unit_damage {
-//                            if unit2.player_id != unit.player_id {
-//                                println!("dead_cost enemy {}", unit2.id);
-//                                damage_enemy += dead_cost;
-//                            } else {
-//                                println!("dead_cost myself {}", unit2.id);
-//                                damage_myself += dead_cost;
-//                            }
-//                        }
-//                    }
                 }
+            }
+
+            if let Some((hit_time, unit2)) = first_hit {
+                bullet_end = bullet_at_tick.add(bullet_velocity.mul(hit_time));
+                bullet_end_tick = tick as f64 + hit_time;
+                unit_hit_player = Some(unit2.player_id);
+                break;
+            }
+        }
 
-//                println!("damage_myself {} damage_enemy {}", damage_myself / parts, damage_enemy / parts);
+        let mut enemy_damage = 0.0;
+        let mut ally_damage = 0.0;
 
-                println!("damage_enemy {} damage_myself {}", damage_enemy / parts as f64, damage_myself / parts as f64);
-                damage_enemy > 0.0 && damage_enemy > damage_myself
+        // ущерб от пули
+        if let Some(unit_hit_player) = unit_hit_player {
+            let damage = weapon.params.bullet.damage as f64;
+            if unit_hit_player != unit.player_id {
+                enemy_damage += damage;
             } else {
-                false
+                ally_damage += damage;
             }
-        } else {
-            false
         }
-    }
 
-    fn damage_unit_by_explosion(unit_position: Vec2F64, explosion: Vec2F64, explosion_radius: f64, unit_height: f64) -> bool {
-        MyStrategy::damage_by_explosion(unit_position.add(Vec2F64 { x: -0.5, y: 0.0 }), explosion.clone(), explosion_radius) ||
-            MyStrategy::damage_by_explosion(unit_position.add(Vec2F64 { x: 0.5, y: 0.0 }), explosion.clone(), explosion_radius) ||
-            MyStrategy::damage_by_explosion(unit_position.add(Vec2F64 { x: -0.5, y: unit_height }), explosion.clone(), explosion_radius) ||
-            MyStrategy::damage_by_explosion(unit_position.add(Vec2F64 { x: 0.5, y: unit_height }), explosion.clone(), explosion_radius)
+        // ущерб от взрыва, вместе с минами, которые он подорвет
+        if let Some(explosion_params) = &weapon.params.explosion {
+            let explosions = chain_explosions(vec![Explosion::new(bullet_end, explosion_params)], &game.mines);
+            for unit2 in &game.units {
+                let unit_position = {
+                    let t = bullet_end_tick % 1.0;
+                    let (position1, position2) = unit_position_at(unit2, bullet_end_tick.floor() as i32);
+                    position1.add(position2.sub(position1).mul(t))
+                };
+                let damage: i32 = explosions.iter()
+                    .filter(|explosion| explosion.hits_unit(unit_position, unit2.size))
+                    .map(|explosion| explosion.damage)
+                    .sum();
+                if unit2.player_id != unit.player_id {
+                    enemy_damage += damage as f64;
+                } else {
+                    ally_damage += damage as f64;
+                }
+            }
+        }
+        (enemy_damage, ally_damage, bullet_end)
     }
 
-    fn damage_by_explosion(position: Vec2F64, explosion: Vec2F64, explosion_radius: f64) -> bool {
-        (position.x - explosion.x).abs() <= explosion_radius && (position.y - explosion.y).abs() <= explosion_radius
+    /// время на поиск для одного игрока: доля оставшегося бюджета игры, поделенного на оставшиеся тики и игроков
+    fn search_time_limit(total_time: u128, game: &Game) -> u128 {
+        let remaining_ticks = (game.properties.max_tick_count - game.current_tick).max(1) as f64;
+        let remaining_ms = GAME_TIME_BUDGET_MS.saturating_sub(total_time) as f64;
+        (remaining_ms / remaining_ticks / game.properties.team_size as f64 * MCTS_TIME_SHARE) as u128
     }
 
-    fn build_all_paths(target_pos: TilePos, paths: &Paths) -> FnvHashMap<(TilePos, VerticalState), i32> {
-        let mut map: FnvHashMap<(TilePos, VerticalState), i32> = FnvHashMap::default();
-        let mut queue = VecDeque::new();
-        paths.incoming
-            .keys()
-            .filter(|(pos, _vertical_state)| *pos == target_pos)
-            .for_each(|(pos, vertical_state)| queue.push_back((*pos, *vertical_state, 0)));
-        let empty_vec = vec![];
-        while !queue.is_empty() {
-            let (pos, vertical_state, ticks) = queue.pop_front().unwrap();
-            match map.entry((pos, vertical_state)) {
-                Vacant(e) => {
-                    e.insert(ticks);
-                }
-                Occupied(mut e) => {
-                    if *e.get() > ticks { e.insert(ticks); } else { continue; }
-                }
+    /// Модель выстрела для поиска: ожидаемые очки (с весом MCTS_SHOT_WEIGHT) за выстрел с клетки через tick тиков -
+    /// урон, но не больше здоровья противника, и kill_score, если выстрел его убивает.
+    /// Противник в предсказанном положении, разброс минимальный (на текущем тике - текущий), стены загораживают.
+    fn shot_value_model<'a>(unit: &'a Unit, paths: &Paths, game: &'a Game) -> impl FnMut(TilePos, i32) -> f64 + 'a {
+        let max_bucket = 20 / MCTS_ENEMY_BUCKET_TICKS;
+        let enemy_positions: Vec<(&Unit, Vec<Vec2F64>)> = game.units.iter()
+            .filter(|unit2| unit2.player_id != unit.player_id)
+            .map(|enemy| (enemy, (0..=max_bucket)
+                .map(|bucket| MyStrategy::estimate_enemy_position(enemy, (bucket * MCTS_ENEMY_BUCKET_TICKS) as f64, paths, game))
+                .collect()))
+            .collect();
+        let mut cache: FnvHashMap<(TilePos, i32, bool), f64> = FnvHashMap::default();
+        move |pos: TilePos, tick: i32| {
+            let weapon = match &unit.weapon {
+                Some(weapon) => weapon,
+                None => return 0.0,
             };
-            paths.incoming.get(&(pos, vertical_state)).unwrap_or_else(|| &empty_vec)
-                .iter()
-                .for_each(|mov| queue.push_back((mov.pos1, mov.vertical_state1, ticks + mov.ticks)));
+            let bucket = ((tick + MCTS_ENEMY_BUCKET_TICKS / 2) / MCTS_ENEMY_BUCKET_TICKS).min(max_bucket);
+            *cache.entry((pos, bucket, tick == 0)).or_insert_with(|| {
+                let from = to_unit_position(pos).add(Vec2F64 { x: 0.0, y: game.properties.unit_size.y / 2.0 });
+                let spread = if tick == 0 { weapon.spread } else { weapon.params.min_spread };
+                let damage = weapon.params.bullet.damage + weapon.params.explosion.as_ref().map_or(0, |explosion| explosion.damage);
+                enemy_positions.iter()
+                    .filter(|(enemy, positions)| Visibility::unit_visible(from, positions[bucket as usize], enemy.size, weapon.params.bullet.size, &game.level))
+                    .map(|(enemy, positions)| {
+                        let center = positions[bucket as usize].add(Vec2F64 { x: 0.0, y: enemy.size.y / 2.0 });
+                        let distance = distance_sqr(from, center).sqrt().max(1e-9);
+                        let half_angle = ((enemy.size.x + enemy.size.y) / 4.0 / distance).atan();
+                        let score = damage.min(enemy.health) + if damage >= enemy.health { game.properties.kill_score } else { 0 };
+                        hit_chance(0.0, spread, 0.0, half_angle) * score as f64 * MCTS_SHOT_WEIGHT
+                    })
+                    .fold(0.0, f64::max)
+            })
         }
-        map
     }
 
-    fn calc_damage(mov: &Move, from_tick: i32, unit_id: i32, bullets_state: &BulletsState, bullets: &Bullets, game: &Game) -> (i32, BulletsState) {
-        // проверим на урон
+    /// номер следующего хода случайного пути: вероятнее ходы, после которых расстояние до цели ближе к target_dist
+    fn sample_move(movs: &[Move], target_map: &DistanceMap, target_dist: i32, rand: &mut Random) -> usize {
+        if !USE_BIASED_SAMPLING || rand.next_f64() < SAMPLING_EPSILON {
+            return rand.next_u32_bounded(movs.len() as u32) as usize;
+        }
+        let costs: Vec<f64> = movs.iter()
+            .map(|mov| target_map.get(&(mov.pos2, mov.vertical_state2)).map_or(f64::INFINITY, |dist| (mov.ticks + (dist - target_dist).abs()) as f64))
+            .collect();
+        let min_cost = costs.iter().cloned().fold(f64::INFINITY, f64::min);
+        if min_cost == f64::INFINITY {
+            // цель недостижима ни одним ходом
+            return rand.next_u32_bounded(movs.len() as u32) as usize;
+        }
+        let weights: Vec<f64> = costs.iter().map(|cost| (-(cost - min_cost) / SAMPLING_TEMPERATURE).exp()).collect();
+        rand.choose_weighted(&weights).unwrap_or(0)
+    }
+
+    /// урон на ходе: positions - положения игрока по тикам хода из прогона модели движения, начиная с тика from_tick
+    fn calc_damage(positions: &[Vec2F64], from_tick: i32, unit_id: i32, bullets_state: &BulletsState, bullets: &Bullets, game: &Game) -> (i32, BulletsState) {
         let mut damage = 0;
         let mut bullets_state = bullets_state.clone();
-        if bullets.need_test(&bullets_state) {
-            for mov_tick in 0..mov.ticks {
-                let micro_ticks = 100;
-                for micro_tick in 0..micro_ticks {
-                    let t = micro_tick as f64 / micro_ticks as f64;
-                    let position = Vec2F64 {
-                        x: mov.pos1.0 as f64 + (mov.pos2.0 as f64 - mov.pos1.0 as f64) * (mov_tick as f64 + t) / mov.ticks as f64 + 0.5,
-                        y: mov.pos1.1 as f64 + (mov.pos2.1 as f64 - mov.pos1.1 as f64) * (mov_tick as f64 + t) / mov.ticks as f64,
-                    };
-
-                    let (bullet_hits, explosion_hits, new_bullets_state) =
-                        bullets.test(position, unit_id, from_tick as f64 + mov_tick as f64 + t, &bullets_state, &game.properties);
-                    bullets_state = new_bullets_state;
-                    bullet_hits.unwrap_or_default().iter().for_each(|hit| damage += hit.1);
-                    explosion_hits.unwrap_or_default().iter().for_each(|hit| damage += hit.1);
-                }
+        // внутри тика игрок движется равномерно
+        for (tick, pair) in positions.windows(2).enumerate() {
+            if !bullets.need_test(&bullets_state) {
+                break;
             }
+            let tick = (from_tick + tick as i32) as f64;
+            let (bullet_hits, explosion_hits, new_bullets_state) =
+                bullets.test((pair[0], pair[1]), unit_id, tick, tick + 1.0, &bullets_state, &game.properties);
+            bullets_state = new_bullets_state;
+            bullet_hits.unwrap_or_default().iter().for_each(|hit| damage += hit.2);
+            explosion_hits.unwrap_or_default().iter().for_each(|hit| damage += hit.2);
         }
         (damage, bullets_state)
     }
 
-    /// возвращает положение на начало тика и на конец тика
-    fn get_unit_position_at_tick(unit: &Unit, path: &[Move], path_tick: i32) -> (Vec2F64, Vec2F64) {
-        let mut result = (unit.position.clone(), unit.position.clone());
-        if !path.is_empty() {
-            let mut tick2 = path_tick;
-            let mut found = false;
-            for i in 1..path.len() {
-                if tick2 < path[i].ticks as i32 {
-                    let position1 = to_unit_position(path[i].pos1);
-                    let position2 = to_unit_position(path[i].pos2);
-                    let t1 = tick2 as f64 / path[i].ticks as f64;
-                    let t2 = (tick2 + 1) as f64 / path[i].ticks as f64;
-                    result = (
-                        position1.add(position2.sub(position1).mul(t1)),
-                        position1.add(position2.sub(position1).mul(t2)),
-                    );
-                    found = true;
-                    break;
-                } else {
-                    tick2 -= path[i].ticks;
+    /// Уклонение: если при текущем действии в ближайшие DODGE_TICKS тиков в нас попадут,
+    /// перебираем действия (скорость, прыжок, спрыгивание), держим каждое DODGE_TICKS тиков в точной модели движения
+    /// и берем то, где урон меньше всего. Возвращает действие, только если оно лучше текущего.
+    fn choose_dodge(unit: &Unit, current: Option<&MoveAction>, bullets: &Bullets, game: &Game) -> Option<MoveAction> {
+        if !bullets.need_test(&BulletsState::new()) {
+            return None;
+        }
+        let current_action = match current {
+            Some(action) => MoveAction { typ: action.typ, velocity: action.velocity, jump: action.jump, jump_down: action.jump_down },
+            None => MoveAction { typ: MoveType::Dodge, velocity: 0.0, jump: false, jump_down: false },
+        };
+        let current_damage = MyStrategy::dodge_damage(unit, &current_action, bullets, game);
+        if current_damage == 0 {
+            return None;
+        }
+        let max_speed = game.properties.unit_max_horizontal_speed;
+        let mut best: Option<(MoveAction, i32)> = None;
+        for velocity in &[0.0, -max_speed, max_speed, -max_speed / 2.0, max_speed / 2.0] {
+            for &(jump, jump_down) in &[(false, false), (true, false), (false, true)] {
+                let action = MoveAction { typ: MoveType::Dodge, velocity: *velocity, jump, jump_down };
+                let damage = MyStrategy::dodge_damage(unit, &action, bullets, game);
+                if best.as_ref().map(|(_, best_damage)| damage < *best_damage).unwrap_or(true) {
+                    best = Some((action, damage));
                 }
             }
-            if !found {
-                result = (to_unit_position(path.last().unwrap().pos2), to_unit_position(path.last().unwrap().pos2));
+        }
+        best.filter(|(_, damage)| *damage < current_damage).map(|(action, _)| action)
+    }
+
+    /// урон за DODGE_TICKS тиков, если все это время держать action
+    fn dodge_damage(unit: &Unit, action: &MoveAction, bullets: &Bullets, game: &Game) -> i32 {
+        let actions: Vec<MoveAction> = (0..DODGE_TICKS)
+            .map(|_| MoveAction { typ: action.typ, velocity: action.velocity, jump: action.jump, jump_down: action.jump_down })
+            .collect();
+        let positions = UnitSim::new(unit).rollout(&actions, DODGE_MICROTICKS, &game.level, &game.properties);
+        let mut bullets_state = BulletsState::new();
+        let mut damage = 0;
+        for tick in 0..DODGE_TICKS {
+            if !bullets.need_test(&bullets_state) {
+                break;
             }
+            let (bullet_hits, explosion_hits, new_bullets_state) =
+                bullets.test(position_at(&positions, tick), unit.id, tick as f64, (tick + 1) as f64, &bullets_state, &game.properties);
+            bullets_state = new_bullets_state;
+            bullet_hits.unwrap_or_default().iter().for_each(|hit| damage += hit.2);
+            explosion_hits.unwrap_or_default().iter().for_each(|hit| damage += hit.2);
         }
-        result
+        damage
+    }
+
+    /// ход возврата на ближайшую клетку графа
+    fn recovery_move(unit: &Unit, paths: &Paths, game: &Game) -> Option<Move> {
+        plan_recovery(unit, game, |pos| paths.outgoing.contains_key(&(pos, VerticalState::Default)))
+    }
+
+    /// Положения игрока на тики 0..=ticks, если он продолжит свой план (прогон модели движения).
+    /// План продолжается с текущего хода; если ход не из плана (recover, уклонение) или плана нет - игрок ничего не делает.
+    fn predict_positions(unit: &Unit, unit_data: &UnitData, ticks: i32, game: &Game) -> Vec<Vec2F64> {
+        let index = unit_data.move_index().filter(|_| unit_data.id == unit.id);
+        let path: &[Move] = if index.is_some() { &unit_data.path } else { &[] };
+        UnitSim::new(unit).follow_path(path, index.unwrap_or(0), ticks, PATH_MICROTICKS, &game.level, &game.properties)
     }
 
     fn draw_unit(unit_position: Vec2F64, color: ColorF32, width: f32, properties: &Properties, debug: &mut crate::Debug) {
@@ -1147,38 +1379,86 @@ impl MyStrategy {
         });
     }
 
+    fn draw_square(center: Vec2F64, radius: f64, color: ColorF32, width: f32, debug: &mut crate::Debug) {
+        let p00 = Vec2F32 { x: (center.x - radius) as f32, y: (center.y - radius) as f32 };
+        let p01 = Vec2F32 { x: (center.x - radius) as f32, y: (center.y + radius) as f32 };
+        let p11 = Vec2F32 { x: (center.x + radius) as f32, y: (center.y + radius) as f32 };
+        let p10 = Vec2F32 { x: (center.x + radius) as f32, y: (center.y - radius) as f32 };
+        for (p1, p2) in [(p00.clone(), p01.clone()), (p01, p11.clone()), (p11, p10.clone()), (p10, p00)].iter() {
+            debug.draw(model::CustomData::Line {
+                p1: p1.clone(),
+                p2: p2.clone(),
+                color: color.clone(),
+                width,
+            });
+        }
+    }
+
     fn estimate_enemy_position(enemy: &Unit, tick: f64, paths: &Paths, game: &Game) -> Vec2F64 {
-        let mut average_position = Vec2F64 { x: 0.0, y: 0.0 };
-        let mut count = 0;
+        MyStrategy::estimate_enemy_positions(enemy, tick, paths, game).iter()
+            .fold(Vec2F64 { x: 0.0, y: 0.0 }, |average, (position, probability)| average.add(position.mul(*probability)))
+    }
+
+    /// Распределение положения противника через tick тиков: все пути по графу равновероятны,
+    /// берем ENEMY_POSITIONS самых вероятных клеток (смещение внутри клетки сохраняем)
+    fn estimate_enemy_positions(enemy: &Unit, tick: f64, paths: &Paths, game: &Game) -> Vec<(Vec2F64, f64)> {
+        let mut counts: FnvHashMap<TilePos, usize> = FnvHashMap::default();
         let mut stack = Vec::new();
-        stack.push((MyStrategy::make_start_node(tile_pos(enemy.position), MyStrategy::get_vertical_state(enemy, game)), 0));
+        stack.push((make_start_node(tile_pos(enemy.position), MyStrategy::get_vertical_state(enemy, game)), 0));
         while let Some((mov, tick2)) = stack.pop() {
             if tick2 as f64 >= tick.min(20.0) {
-                average_position = average_position.add(to_unit_position(mov.pos2));
-                count += 1;
+                *counts.entry(mov.pos2).or_insert(0) += 1;
                 continue;
             }
             for mov2 in paths.outgoing.get(&(mov.pos2, mov.vertical_state2)).unwrap_or(&Vec::new()) {
                 stack.push((mov2.clone(), tick2 + mov2.ticks));
             }
         }
-        if count == 0 {
-            enemy.position
-        } else {
-            average_position.mul(1.0 / count as f64)
+        if counts.is_empty() {
+            return vec![(enemy.position, 1.0)];
         }
+        let mut counts: Vec<(TilePos, usize)> = counts.into_iter().collect();
+        counts.sort_by_key(|(pos, count)| (std::cmp::Reverse(*count), *pos));
+        counts.truncate(ENEMY_POSITIONS);
+        let total: usize = counts.iter().map(|(_, count)| count).sum();
+        let offset = enemy.position.sub(to_unit_position(tile_pos(enemy.position)));
+        counts.iter()
+            .map(|(pos, count)| (to_unit_position(*pos).add(offset), *count as f64 / total as f64))
+            .collect()
     }
 
-    fn suicide_is_effective(planting_unit_position: Vec2F64, my_player_id: i32, suicide_damage: i32, game: &Game) -> bool {
-        fn in_mine_explosion_radius(unit: &Unit, planting_unit_position: Vec2F64, properties: &Properties) -> bool {
-            let mine_position = planting_unit_position;
-            (unit.position.x - mine_position.x).abs() <= properties.mine_explosion_params.radius &&
-                (unit.position.y - mine_position.y).abs() <= properties.mine_explosion_params.radius
+    /// взрывы при подрыве себя: все мины в mine_position и ракета, выпущенная в пол
+    fn suicide_explosions(mine_position: Vec2F64, unit: &Unit, properties: &Properties) -> Vec<Explosion> {
+        let mut explosions: Vec<Explosion> = (0..unit.mines)
+            .map(|_| Explosion::of_mine(mine_position, &properties.mine_explosion_params, properties))
+            .collect();
+        if let Some(weapon) = &unit.weapon {
+            if let Some(explosion_params) = &weapon.params.explosion {
+                let rocket_position = mine_position.add(Vec2F64 { x: 0.0, y: weapon.params.bullet.size / 2.0 });
+                explosions.push(Explosion::new(rocket_position, explosion_params));
+            }
         }
+        explosions
+    }
 
-        game.units.iter()
-            .filter(|unit2| unit2.player_id != my_player_id)
-            .any(|unit2| unit2.health <= suicide_damage && in_mine_explosion_radius(unit2, planting_unit_position, &game.properties))
+    /// подрыв эффективен, если убьем противников больше, чем своих (не считая себя)
+    fn suicide_is_effective(planting_unit_position: Vec2F64, unit: &Unit, game: &Game) -> bool {
+        let explosions = chain_explosions(MyStrategy::suicide_explosions(planting_unit_position, unit, &game.properties), &game.mines);
+        let units: Vec<(i32, Vec2F64, Vec2F64)> = game.units.iter()
+            .map(|unit2| (unit2.id, if unit2.id == unit.id { planting_unit_position } else { unit2.position }, unit2.size))
+            .collect();
+        let damage = explosions_damage(&explosions, &units);
+        let killed = |unit2: &&Unit| damage.get(&unit2.id).copied().unwrap_or(0) >= unit2.health;
+
+        let killed_enemies = game.units.iter()
+            .filter(|unit2| unit2.player_id != unit.player_id)
+            .filter(killed)
+            .count();
+        let killed_friends = game.units.iter()
+            .filter(|unit2| unit2.player_id == unit.player_id && unit2.id != unit.id)
+            .filter(killed)
+            .count();
+        killed_enemies > 0 && killed_enemies > killed_friends
     }
 }
 
@@ -1186,25 +1466,6 @@ fn distance_sqr(a: Vec2F64, b: Vec2F64) -> f64 {
     (a.x - b.x).powi(2) + (a.y - b.y).powi(2)
 }
 
-fn segments_intersection(a1: Vec2F64, a2: Vec2F64, b1: Vec2F64, b2: Vec2F64) -> Option<Vec2F64> {
-    let d = (a1.x - a2.x) * (b2.y - b1.y) - (a1.y - a2.y) * (b2.x - b1.x);
-    let da = (a1.x - b1.x) * (b2.y - b1.y) - (a1.y - b1.y) * (b2.x - b1.x);
-    let db = (a1.x - a2.x) * (a1.y - b1.y) - (a1.y - a2.y) * (a1.x - b1.x);
-
-    if d.abs() < 1e-9 {
-        return None; // almost parallel lines
-    }
-
-    let ta = da / d;
-    let tb = db / d;
-
-    if ta < 0.0 || ta > 1.0 || tb < 0.0 || tb > 1.0 {
-        return None;
-    }
-
-    Some(Vec2F64 { x: a1.x + ta * (a2.x - a1.x), y: a1.y + ta * (a2.y - a1.y) })
-}
-
 fn tile_pos(position: Vec2F64) -> TilePos {
     (position.x as isize, position.y as isize)
 }
@@ -1213,163 +1474,187 @@ fn to_unit_position(pos: TilePos) -> Vec2F64 {
     Vec2F64 { x: pos.0 as f64 + 0.5, y: pos.1 as f64 }
 }
 
-fn delta_angle(angle_from: f64, angle_to: f64) -> f64 {
-    normalize_angle(angle_to - angle_from)
-}
+// урон на ходе: (положения игрока на ходе, тик начала, состояние пуль) -> (урон, состояние пуль после хода)
+type DamageCache = FnvHashMap<(Vec<(u64, u64)>, i32, BulletsState), (i32, BulletsState)>;
 
-fn normalize_angle(mut angle: f64) -> f64 {
-    while angle > std::f64::consts::PI {
-        angle -= 2.0 * std::f64::consts::PI;
-    }
-    while angle < -std::f64::consts::PI {
-        angle += 2.0 * std::f64::consts::PI;
-    }
-    angle
+// положения как ключ таблицы: у путей с общим началом прогон дает те же самые числа
+fn position_key(positions: &[Vec2F64]) -> Vec<(u64, u64)> {
+    positions.iter().map(|position| (position.x.to_bits(), position.y.to_bits())).collect()
 }
 
-struct Paths {
-    outgoing: FnvHashMap<(TilePos, VerticalState), Vec<Move>>,
-    incoming: FnvHashMap<(TilePos, VerticalState), Vec<Move>>,
+/// Ожидаемый урон от выстрела и его дисперсия: противникам и своим (включая себя)
+#[derive(Default)]
+struct ShotEstimate {
+    enemy_damage: f64,
+    enemy_damage_sqr: f64,
+    ally_damage: f64,
+    ally_damage_sqr: f64,
 }
 
-impl Paths {
-    fn update_paths(&mut self, pos: TilePos, vertical_state: VerticalState, game: &Game) {
-        let now = Instant::now();
-        let mut queue = VecDeque::new();
-        queue.push_back(MyStrategy::make_start_node(pos, vertical_state));
-        let mut count_o = 0;
-        let mut count_i = 0;
-        while !queue.is_empty() {
-            let mov = queue.pop_front().unwrap();
-            if self.outgoing.get(&(mov.pos2, mov.vertical_state2)).is_some() {
-                continue;
-            }
-            self.outgoing.insert((mov.pos2, mov.vertical_state2), Vec::new());
-            for movement in get_movements() {
-                if let Some(mov2) = movement.can_move(mov.pos2, mov.vertical_state2, &game.level, &game.properties) {
-                    let outgoing_entry = self.outgoing.entry((mov2.pos1, mov2.vertical_state1)).or_insert_with(|| Vec::new());
-                    if !outgoing_entry.contains(&mov2) {
-                        outgoing_entry.push(mov2.clone());
-                        count_o += 1;
-                    }
-                    let incoming_entry = self.incoming.entry((mov2.pos2, mov2.vertical_state2)).or_insert_with(|| Vec::new());
-                    if !incoming_entry.contains(&mov2) {
-                        incoming_entry.push(mov2.clone());
-                        count_i += 1;
-                    }
-                    if self.outgoing.get(&(mov2.pos2, mov2.vertical_state2)).is_none() {
-                        queue.push_back(mov2);
-                    }
-                }
-            }
-        }
-        println!("outgoing {} incoming {} count_o {} count_i {}, {} ms", self.outgoing.len(), self.incoming.len(), count_o, count_i, now.elapsed().as_millis());
+impl ShotEstimate {
+    fn add(&mut self, enemy_damage: f64, ally_damage: f64, probability: f64) {
+        self.enemy_damage += enemy_damage * probability;
+        self.enemy_damage_sqr += enemy_damage * enemy_damage * probability;
+        self.ally_damage += ally_damage * probability;
+        self.ally_damage_sqr += ally_damage * ally_damage * probability;
     }
-}
 
-#[derive(Clone)]
-struct Bullets {
-    bullets: Vec<(Bullet, Vec2F64, f64)>, // Bullet, end pos, end tick (with microticks)
-}
-
-impl Bullets {
-    fn new(game: &Game) -> Bullets {
-        let mut bullets = Vec::new();
-        for bullet in &game.bullets {
-            let (end, tick) = MyStrategy::bullet_end(bullet.position, bullet.velocity, &bullet.weapon_type, &game.level, &game.properties);
-            bullets.push((bullet.clone(), end, tick));
-        }
-        Bullets {
-            bullets
-        }
+    fn enemy_variance(&self) -> f64 {
+        (self.enemy_damage_sqr - self.enemy_damage * self.enemy_damage).max(0.0)
     }
 
-    /// Оценивает попадания и убирает пули, которые попали в игрока или в стены/границы
-    fn test(&self, unit_position: Vec2F64, unit_id: i32, tick: f64, bullets_state: &BulletsState, properties: &Properties)
-            -> (Option<Vec<(Vec2F64, i32)>>, Option<Vec<(Vec2F64, i32, f64)>>, BulletsState) { // bullet hits (pos, damage), explosion hits (pos, damage, radius), removed bullets
-        let unit_center = unit_position.add(Vec2F64 { x: 0.0, y: properties.unit_size.y / 2.0 });
-
-        let mut bullet_hits = None;
-        let mut explosion_hits = None;
+    fn ally_variance(&self) -> f64 {
+        (self.ally_damage_sqr - self.ally_damage * self.ally_damage).max(0.0)
+    }
 
-        let mut new_bullet_state = bullets_state.clone();
+    /// стреляем, если ожидаемый урон противнику больше урона своим с запасом в risk стандартных отклонений
+    fn is_worth(&self, risk: f64) -> bool {
+        self.enemy_damage > 0.0 && self.enemy_damage > self.ally_damage + risk * self.ally_variance().sqrt()
+    }
+}
 
-        for (index, bullet) in self.bullets.iter().enumerate() {
-            if bullets_state.is_bullet_removed(index) {
-                continue;
-            }
+#[cfg(test)]
+mod tests {
+    use model::{Game, Vec2F64, WeaponType};
+
+    use crate::path::{make_start_node, DistanceMap, Move, MoveType, Paths, VerticalState};
+    use crate::rand::Random;
+    use crate::test_utils;
+
+    use super::{MyStrategy, UnitData, SAMPLING_EPSILON, SHOT_PREDICTION_TICKS};
+
+    const CORRIDOR: [&str; 5] = [
+        "##########################",
+        "#........................#",
+        "#........................#",
+        "#........................#",
+        "##########################",
+    ];
+
+    // свой стрелок на (2.5, 1), напарник на (teammate_x, 1), противник на (14.5, 1)
+    fn corridor_game(weapon_type: WeaponType, teammate_x: f64) -> Game {
+        let properties = test_utils::properties();
+        let units = vec![
+            test_utils::unit(1, 1, 2.5, 1.0, Some(weapon_type), &properties),
+            test_utils::unit(2, 1, teammate_x, 1.0, None, &properties),
+            test_utils::unit(3, 2, 14.5, 1.0, None, &properties),
+        ];
+        test_utils::game(test_utils::level(&CORRIDOR), units)
+    }
 
-            let mut explode = false;
+    fn unit_data(id: i32, path: Vec<Move>) -> UnitData {
+        UnitData {
+            id,
+            move_: path.get(1).cloned(),
+            path,
+            path_start_tick: 0,
+            last_position: Vec2F64 { x: -1.0, y: -1.0 },
+            mine_plan: None,
+            retreating: false,
+            rand: Random::new(0),
+            mcts_tree: None,
+            hold_fire: None,
+        }
+    }
 
-            let bullet_position = Vec2F64 {
-                x: bullet.0.position.x as f64 + bullet.0.velocity.x * tick / properties.ticks_per_second,
-                y: bullet.0.position.y as f64 + bullet.0.velocity.y * tick / properties.ticks_per_second,
-            };
+    // исход выстрела вправо по коридору, напарник идет по плану path (пустой - стоит)
+    fn shot_right(game: &Game, path: Vec<Move>) -> (f64, f64) {
+        let unit = &game.units[0];
+        let teammate = &game.units[1];
+        let positions = vec![(teammate.id, MyStrategy::predict_positions(teammate, &unit_data(teammate.id, path), SHOT_PREDICTION_TICKS, game))];
+        let bullet_from = Vec2F64 { x: unit.position.x, y: unit.position.y + unit.size.y / 2.0 };
+        let (enemy_damage, ally_damage, _) = MyStrategy::shot_outcome(unit, unit.weapon.as_ref().unwrap(), bullet_from,
+                                                                      Vec2F64 { x: 1.0, y: 0.0 }, &positions, game);
+        (enemy_damage, ally_damage)
+    }
 
-            if bullet.2 < tick {
-                new_bullet_state.remove_bullet(index);
-                explode = bullet.0.explosion_params.is_some();
-            } else {
-                // попадание пули
-                // на свою пулю наткнуться нельзя
-                let half_bullet_size = bullet.0.size / 2.0;
-                if (unit_center.x - bullet_position.x).abs() <= properties.unit_size.x / 2.0 + half_bullet_size &&
-                    (unit_center.y - bullet_position.y).abs() <= properties.unit_size.y / 2.0 + half_bullet_size &&
-                    bullet.0.unit_id != unit_id {
-                    new_bullet_state.remove_bullet(index);
-                    explode = bullet.0.explosion_params.is_some();
-                    if bullet_hits.is_none() {
-                        bullet_hits = Some(Vec::new());
-                    }
-                    bullet_hits.as_mut().unwrap().push((bullet_position, bullet.0.damage));
-                }
-            }
+    // план напарника: идти вправо на steps клеток
+    fn walk_right(game: &Game, steps: isize) -> Vec<Move> {
+        let teammate = &game.units[1];
+        let start = (teammate.position.x as isize, teammate.position.y as isize);
+        let mut paths = Paths::new();
+        paths.update_paths(start, VerticalState::Default, &game.level, &game.properties);
+        let mut path = vec![make_start_node(start, VerticalState::Default)];
+        for _ in 0..steps {
+            let last = path.last().unwrap();
+            let mov = paths.outgoing[&(last.pos2, last.vertical_state2)].iter()
+                .find(|mov| mov.typ == MoveType::WalkRight)
+                .unwrap()
+                .clone();
+            path.push(mov);
+        }
+        path
+    }
 
-            // взрыв
-            if explode {
-                let radius = bullet.0.explosion_params.as_ref().unwrap().radius;
-                if (unit_center.x - bullet_position.x).abs() <= properties.unit_size.x / 2.0 + radius &&
-                    (unit_center.y - bullet_position.y).abs() <= properties.unit_size.y / 2.0 + radius {
-                    if explosion_hits.is_none() {
-                        explosion_hits = Some(Vec::new());
-                    }
-                    explosion_hits.as_mut().unwrap().push((bullet_position, bullet.0.explosion_params.as_ref().unwrap().damage, bullet.0.explosion_params.as_ref().unwrap().radius));
-                }
-            }
-        };
+    #[test]
+    fn bullet_hits_teammate_in_line_of_fire() {
+        let game = corridor_game(WeaponType::Pistol, 8.5);
+        let (enemy_damage, ally_damage) = shot_right(&game, Vec::new());
+        assert_eq!(enemy_damage, 0.0);
+        assert_eq!(ally_damage, 20.0);
+    }
 
-        (bullet_hits, explosion_hits, new_bullet_state)
+    #[test]
+    fn bullet_stops_at_enemy_before_teammate() {
+        let game = corridor_game(WeaponType::Pistol, 17.5);
+        let (enemy_damage, ally_damage) = shot_right(&game, Vec::new());
+        assert_eq!(enemy_damage, 20.0);
+        assert_eq!(ally_damage, 0.0);
     }
 
-    fn need_test(&self, bullets_state: &BulletsState) -> bool {
-        self.bullets.len() > bullets_state.count_removed_bullets()
+    #[test]
+    fn explosion_hits_standing_teammate() {
+        let game = corridor_game(WeaponType::RocketLauncher, 16.5);
+        let (enemy_damage, ally_damage) = shot_right(&game, Vec::new());
+        assert_eq!(enemy_damage, 30.0 + 50.0);
+        assert_eq!(ally_damage, 50.0);
     }
-}
 
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
-struct BulletsState {
-    removed_bullets: Vec<bool>, // индекс пули в Bullets
-}
+    #[test]
+    fn explosion_misses_teammate_leaving_by_plan() {
+        // ракета летит до противника больше 30 тиков, за это время напарник уходит из радиуса взрыва
+        let game = corridor_game(WeaponType::RocketLauncher, 16.5);
+        let (enemy_damage, ally_damage) = shot_right(&game, walk_right(&game, 6));
+        assert_eq!(enemy_damage, 30.0 + 50.0);
+        assert_eq!(ally_damage, 0.0);
+    }
 
-impl BulletsState {
-    fn new() -> Self {
-        Self {
-            removed_bullets: vec![]
-        }
+    // шаги влево и вправо из (5, 1) и карта, где после шага влево до цели 50 тиков, а после шага вправо - 0
+    fn left_right_moves() -> (Vec<Move>, DistanceMap) {
+        let game = corridor_game(WeaponType::Pistol, 8.5);
+        let mut paths = Paths::new();
+        paths.update_paths((5, 1), VerticalState::Default, &game.level, &game.properties);
+        let movs: Vec<Move> = [MoveType::WalkLeft, MoveType::WalkRight].iter()
+            .map(|typ| paths.outgoing[&((5, 1), VerticalState::Default)].iter().find(|mov| mov.typ == *typ).unwrap().clone())
+            .collect();
+        let mut target_map = DistanceMap::default();
+        target_map.insert((movs[0].pos2, movs[0].vertical_state2), 50);
+        target_map.insert((movs[1].pos2, movs[1].vertical_state2), 0);
+        (movs, target_map)
     }
 
-    fn is_bullet_removed(&self, index: usize) -> bool {
-        *self.removed_bullets.get(index).unwrap_or(&false)
+    // сколько раз из 1000 выбран шаг влево
+    fn count_left(movs: &[Move], target_map: &DistanceMap, target_dist: i32) -> usize {
+        let mut rand = Random::new(7);
+        (0..1000).filter(|_| MyStrategy::sample_move(movs, target_map, target_dist, &mut rand) == 0).count()
     }
 
-    fn remove_bullet(&mut self, index: usize) {
-        while self.removed_bullets.len() <= index {
-            self.removed_bullets.push(false);
-        }
-        self.removed_bullets[index] = true;
+    #[test]
+    fn sampling_prefers_moves_to_target_distance() {
+        let (movs, target_map) = left_right_moves();
+        // разница в 50 тиков: без равновероятного выбора почти всегда лучший ход, с ним - в половине случаев
+        let expected_other = (1000.0 * SAMPLING_EPSILON / 2.0) as usize;
+        let right = 1000 - count_left(&movs, &target_map, 0);
+        assert!(right > 1000 - expected_other * 2 && right < 1000 - expected_other / 2, "right {}", right);
+        // держимся на расстоянии 50 тиков - теперь лучше шаг влево
+        let left = count_left(&movs, &target_map, 50);
+        assert!(left > 1000 - expected_other * 2 && left < 1000 - expected_other / 2, "left {}", left);
     }
 
-    fn count_removed_bullets(&self) -> usize {
-        self.removed_bullets.iter().filter(|b| **b).count()
+    #[test]
+    fn sampling_is_uniform_when_target_unreachable() {
+        let (movs, _) = left_right_moves();
+        let left = count_left(&movs, &DistanceMap::default(), 0);
+        assert!(left > 400 && left < 600, "left {}", left);
     }
-}
\ No newline at end of file
+}
@@ -1,6 +1,6 @@
 use std::cmp::{Ordering, PartialOrd};
 
-#[derive(PartialEq, PartialOrd)]
+#[derive(PartialEq)]
 pub struct NonNan(f64);
 
 #[allow(dead_code)]
@@ -20,8 +20,14 @@ impl NonNan {
 
 impl Eq for NonNan {}
 
+impl PartialOrd for NonNan {
+    fn partial_cmp(&self, other: &NonNan) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl Ord for NonNan {
     fn cmp(&self, other: &NonNan) -> Ordering {
-        self.partial_cmp(other).unwrap()
+        self.0.partial_cmp(&other.0).unwrap()
     }
 }
@@ -1,5 +1,9 @@
+use std::collections::hash_map::Entry::{Occupied, Vacant};
+use std::collections::VecDeque;
 use std::rc::Rc;
+use std::time::Instant;
 
+use model::JumpState;
 use model::Level;
 use model::Properties;
 use model::Tile;
@@ -7,6 +11,9 @@ use model::Vec2F64;
 use std::hash::{Hash, Hasher};
 use std::fmt::{Debug, Formatter, Error};
 
+use crate::fnv_hash::FnvHashMap;
+use crate::unit_sim::UnitSim;
+
 // константы для упрощения кода
 const MAX_HORIZONTAL_SPEED: f64 = 10.0;
 const FALL_SPEED: f64 = 10.0;
@@ -16,6 +23,12 @@ const TICKS_PER_SECOND: f64 = 60.0; // properties.ticks_per_second
 const HORIZONTAL_EPSILON: f64 = 0.049;
 // точность достижения точки, должна гарантировать, что игрок целиком внутри квадрата по ширине
 const VERTICAL_EPSILON: f64 = 0.2; // точность достижения точки, любое вертикальное движение должно попадать в этот диапазон (PadJump отдельно)
+// шагов модели движения на тик при проверке дальних ходов
+const MOVE_CHECK_MICROTICKS: usize = 5;
+// во сколько раз проверка хода может затянуться по сравнению с оценкой
+const MOVE_CHECK_TICKS_FACTOR: f64 = 3.0;
+// прыжок с батута останавливается лестницей над следующей клеткой (выключено)
+const PAD_JUMP_LADDER_STOP: bool = false;
 
 pub type TilePos = (isize, isize);
 
@@ -34,6 +47,8 @@ pub enum MoveType {
     Start,
     Recover,
     MineSuicide,
+    // уклонение от пуль вне графа движений
+    Dodge,
     WalkLeft,
     WalkRight,
     LadderUp,
@@ -57,6 +72,11 @@ pub enum MoveType {
     PadJump2Right,
     PadJumpUp,
     PadJumpStop,
+    JumpArcLeft,
+    JumpArcRight,
+    PlatformDrop,
+    PlatformDropLeft,
+    PlatformDropRight,
 }
 
 pub enum ControlResult {
@@ -174,17 +194,51 @@ impl TileMovement for WalkSideMovement {
     }
 }
 
-fn target_reached(position: Vec2F64, target: TilePos, vertical_state: VerticalState) -> bool {
+pub fn target_reached(position: Vec2F64, target: TilePos, vertical_state: VerticalState) -> bool {
+    (position.x - (target.0 as f64 + 0.5)).abs() < HORIZONTAL_EPSILON && height_reached(position, target, vertical_state)
+}
+
+fn height_reached(position: Vec2F64, target: TilePos, vertical_state: VerticalState) -> bool {
     let vertical_epsilon = match vertical_state {
         VerticalState::Default => VERTICAL_EPSILON,
         VerticalState::Jump(_) => VERTICAL_EPSILON,
         VerticalState::PadJump(_) => 2.0 * VERTICAL_EPSILON,
     };
-    (position.x - (target.0 as f64 + 0.5)).abs() < HORIZONTAL_EPSILON && position.y >= target.1 as f64 &&
-        (position.y - target.1 as f64) < vertical_epsilon
+    position.y >= target.1 as f64 && (position.y - target.1 as f64) < vertical_epsilon
+}
+
+/// Можно ли пройти центр клетки в конце хода mov не тормозя: следующий ход плана next продолжает движение в ту же сторону.
+/// Только ходьба и падение: прыжки рассчитаны на старт из центра клетки.
+pub fn can_keep_speed(mov: &Move, next: &Move) -> bool {
+    let direction = (mov.pos2.0 - mov.pos1.0).signum();
+    direction != 0 && next.pos1 == mov.pos2 && (next.pos2.0 - next.pos1.0).signum() == direction &&
+        matches!(mov.typ, MoveType::WalkLeft | MoveType::WalkRight | MoveType::FallLeft | MoveType::FallRight) &&
+        matches!(next.typ, MoveType::WalkLeft | MoveType::WalkRight | MoveType::FallLeft | MoveType::FallRight |
+            MoveType::FallEdgeLeft | MoveType::FallEdgeRight)
+}
+
+/// Управление ходом mov с учетом следующего хода плана.
+/// Если можно не тормозить (can_keep_speed), до центра клетки идем на полной скорости,
+/// а ход считаем выполненным, как только центр проскочили на нужной высоте.
+/// Отклонение от хода по-прежнему определяет управление самого хода (Recover).
+pub fn smooth_control(mov: &Move, next: Option<&Move>, position: Vec2F64, vertical_state: VerticalState) -> ControlResult {
+    let result = (mov.control)(position, vertical_state);
+    if !next.map(|next| can_keep_speed(mov, next)).unwrap_or(false) {
+        return result;
+    }
+    let direction = (mov.pos2.0 - mov.pos1.0).signum() as f64;
+    let passed = (position.x - (mov.pos2.0 as f64 + 0.5)) * direction > -HORIZONTAL_EPSILON;
+    match result {
+        ControlResult::MoveAction(_) if passed && height_reached(position, mov.pos2, vertical_state) => ControlResult::TargetReached,
+        ControlResult::MoveAction(action) if !passed => ControlResult::MoveAction(MoveAction {
+            velocity: MAX_HORIZONTAL_SPEED * direction,
+            ..action
+        }),
+        result => result,
+    }
 }
 
-fn choose_horizontal_speed(pos: f64, target: f64) -> f64 {
+pub fn choose_horizontal_speed(pos: f64, target: f64) -> f64 {
     let delta = (pos - target).abs();
     let speed = if delta < MAX_HORIZONTAL_SPEED / TICKS_PER_SECOND {
         delta * TICKS_PER_SECOND
@@ -827,7 +881,7 @@ impl TileMovement for PadJumpUpMovement {
             Tile::Wall => return None,
             Tile::Ladder => VerticalState::Default,
             Tile::Empty | Tile::Platform => {
-                if false && level.tiles[new_pos.0 as usize][(new_pos.1 + 1) as usize] == Tile::Ladder {
+                if PAD_JUMP_LADDER_STOP && level.tiles[new_pos.0 as usize][(new_pos.1 + 1) as usize] == Tile::Ladder {
                     // клетка выше тоже может быть лестницей и остановить прыжок
                     VerticalState::Default
                 } else {
@@ -912,6 +966,327 @@ impl TileMovement for PadJumpStopMovement {
     }
 }
 
+// прыжок на несколько клеток вбок (через яму или препятствие) с приземлением на delta_y клеток выше или ниже:
+// бежим к цели и держим прыжок, пока не поднимемся на нужную высоту, потом отпускаем и падаем на цель
+// ......
+// ..P...
+// ..P..P
+// ##...P
+// ###.##
+struct JumpArcMovement {
+    delta: isize,
+    delta_y: isize,
+}
+
+impl TileMovement for JumpArcMovement {
+    fn can_move(&self, tile_pos: (isize, isize), vertical_state: VerticalState, level: &Level, properties: &Properties) -> Option<Move> {
+        // на 0-1 клетку есть обычные прыжки
+        if self.delta.abs() < 2 {
+            return None;
+        }
+        match vertical_state {
+            VerticalState::Default => {}
+            VerticalState::Jump(_) => return None,
+            VerticalState::PadJump(_) => return None
+        };
+        // прыгаем с пола, на лестнице прыжок другой
+        match level.tiles[tile_pos.0 as usize][(tile_pos.1 - 1) as usize] {
+            Tile::Wall | Tile::Platform => {}
+            Tile::Empty | Tile::JumpPad | Tile::Ladder => return None,
+        };
+        if unit_is_on_ladder(tile_pos, level) {
+            return None;
+        }
+        let new_pos = (tile_pos.0 + self.delta, tile_pos.1 + self.delta_y);
+        if !check_possible_location(new_pos, level, true, true) {
+            return None;
+        }
+        // верхняя часть игрока
+        if !check_possible_location((new_pos.0, new_pos.1 + 1), level, true, true) {
+            return None;
+        }
+        // приземляемся на пол
+        match level.tiles[new_pos.0 as usize][(new_pos.1 - 1) as usize] {
+            Tile::Wall | Tile::Platform => {}
+            Tile::Empty | Tile::JumpPad | Tile::Ladder => return None,
+        };
+        // по ровному полу дойдем и пешком
+        let step = self.delta.signum();
+        if self.delta_y == 0 && (1..self.delta.abs()).all(|i| {
+            let column = tile_pos.0 + i * step;
+            matches!(level.tiles[column as usize][(tile_pos.1 - 1) as usize], Tile::Wall | Tile::Platform) &&
+                check_possible_location((column, tile_pos.1), level, true, true) &&
+                check_possible_location((column, tile_pos.1 + 1), level, true, true)
+        }) {
+            return None;
+        }
+        // высота подъема: за подъем и падение пробегаем delta клеток
+        let run_time = self.delta.abs() as f64 / properties.unit_max_horizontal_speed;
+        let rise = (run_time + self.delta_y as f64 / properties.unit_fall_speed) /
+            (1.0 / properties.unit_jump_speed + 1.0 / properties.unit_fall_speed);
+        if rise < self.delta_y.max(0) as f64 || rise > properties.unit_jump_time * properties.unit_jump_speed {
+            return None;
+        }
+        // прыжок держим, пока не пробежим по горизонтали столько, сколько занимает подъем
+        let rise_distance = rise / properties.unit_jump_speed * properties.unit_max_horizontal_speed;
+        let start_x = tile_pos.0 as f64 + 0.5;
+        let columns = (tile_pos.0.min(new_pos.0), tile_pos.0.max(new_pos.0));
+        let rows = (tile_pos.1.min(new_pos.1), tile_pos.1 + rise.ceil() as isize + 1);
+        let move_type = if self.delta < 0 { MoveType::JumpArcLeft } else { MoveType::JumpArcRight };
+        let control: Rc<dyn Fn(Vec2F64, VerticalState) -> ControlResult> = Rc::new(move |position: Vec2F64, vertical_state: VerticalState| {
+            if target_reached(position, new_pos, vertical_state) {
+                return ControlResult::TargetReached;
+            }
+            let pos = (position.x as isize, position.y as isize);
+            if pos.0 < columns.0 || pos.0 > columns.1 || pos.1 < rows.0 || pos.1 > rows.1 {
+                return ControlResult::Recover;
+            }
+            ControlResult::MoveAction(MoveAction {
+                typ: move_type,
+                velocity: choose_horizontal_speed(position.x, new_pos.0 as f64 + 0.5),
+                jump: (position.x - start_x).abs() < rise_distance,
+                jump_down: false,
+            })
+        });
+        let estimate = run_time + (rise - self.delta_y as f64) / properties.unit_fall_speed;
+        let ticks = simulate_move(control.as_ref(), tile_pos, estimate, level, properties)?;
+        Some(Move {
+            typ: move_type,
+            pos1: tile_pos,
+            pos2: new_pos,
+            ticks,
+            vertical_state1: vertical_state,
+            vertical_state2: VerticalState::Default,
+            control,
+        })
+    }
+}
+
+// спрыгивание сквозь платформу под ногами (jump_down) до первого пола ниже, со сдвигом на delta
+// .P.
+// .P.
+// ^^^
+// ...
+// ...
+// #.#
+struct PlatformDropMovement {
+    delta: isize
+}
+
+impl TileMovement for PlatformDropMovement {
+    fn can_move(&self, tile_pos: (isize, isize), vertical_state: VerticalState, level: &Level, properties: &Properties) -> Option<Move> {
+        match vertical_state {
+            VerticalState::Default => {}
+            VerticalState::Jump(_) => return None,
+            VerticalState::PadJump(_) => return None
+        };
+        match level.tiles[tile_pos.0 as usize][(tile_pos.1 - 1) as usize] {
+            Tile::Platform => {}
+            _ => return None,
+        };
+        if unit_is_on_ladder(tile_pos, level) {
+            return None;
+        }
+        let column = tile_pos.0 + self.delta;
+        if self.delta != 0 && (!check_possible_location((column, tile_pos.1), level, true, true) ||
+            !check_possible_location((column, tile_pos.1 + 1), level, true, true)) {
+            return None;
+        }
+        // первый пол под платформой
+        let mut y = tile_pos.1 - 1;
+        loop {
+            if !check_possible_location((column, y), level, true, true) {
+                return None;
+            }
+            match level.tiles[column as usize][(y - 1) as usize] {
+                Tile::Wall | Tile::Platform => break,
+                _ => y -= 1,
+            }
+        }
+        // на одну клетку вниз падает FallMovement
+        if y > tile_pos.1 - 2 {
+            return None;
+        }
+        let new_pos = (column, y);
+        let platform_y = tile_pos.1 as f64;
+        let columns = (tile_pos.0.min(new_pos.0), tile_pos.0.max(new_pos.0));
+        let move_type = if self.delta < 0 { MoveType::PlatformDropLeft } else if self.delta > 0 { MoveType::PlatformDropRight } else { MoveType::PlatformDrop };
+        let control: Rc<dyn Fn(Vec2F64, VerticalState) -> ControlResult> = Rc::new(move |position: Vec2F64, vertical_state: VerticalState| {
+            if target_reached(position, new_pos, vertical_state) {
+                return ControlResult::TargetReached;
+            }
+            let pos = (position.x as isize, position.y as isize);
+            if pos.0 < columns.0 || pos.0 > columns.1 || pos.1 < new_pos.1 || pos.1 > tile_pos.1 {
+                return ControlResult::Recover;
+            }
+            ControlResult::MoveAction(MoveAction {
+                typ: move_type,
+                velocity: choose_horizontal_speed(position.x, new_pos.0 as f64 + 0.5),
+                jump: false,
+                // спрыгиваем только с этой платформы, на следующей приземлимся
+                jump_down: position.y > platform_y - 0.5,
+            })
+        });
+        let estimate = (tile_pos.1 - y) as f64 / properties.unit_fall_speed + self.delta.abs() as f64 / properties.unit_max_horizontal_speed;
+        let ticks = simulate_move(control.as_ref(), tile_pos, estimate, level, properties)?;
+        Some(Move {
+            typ: move_type,
+            pos1: tile_pos,
+            pos2: new_pos,
+            ticks,
+            vertical_state1: vertical_state,
+            vertical_state2: VerticalState::Default,
+            control,
+        })
+    }
+}
+
+/// Проверка дальнего хода прогоном модели движения: игрок стоит в центре клетки tile_pos,
+/// управление хода должно довести его до цели не дольше, чем за оценку estimate (в секундах) с запасом.
+/// Возвращает, за сколько тиков дошли.
+fn simulate_move(control: &dyn Fn(Vec2F64, VerticalState) -> ControlResult, tile_pos: TilePos, estimate: f64,
+                 level: &Level, properties: &Properties) -> Option<i32> {
+    let mut sim = UnitSim {
+        position: Vec2F64 { x: tile_pos.0 as f64 + 0.5, y: tile_pos.1 as f64 },
+        size: properties.unit_size,
+        jump_state: JumpState {
+            can_jump: true,
+            speed: properties.unit_jump_speed,
+            max_time: properties.unit_jump_time,
+            can_cancel: true,
+        },
+    };
+    let max_ticks = (estimate * properties.ticks_per_second * MOVE_CHECK_TICKS_FACTOR).ceil() as i32 + 1;
+    for tick in 0..max_ticks {
+        match control(sim.position, sim.vertical_state(properties)) {
+            ControlResult::TargetReached => return Some(tick.max(1)),
+            ControlResult::Recover => return None,
+            ControlResult::MoveAction(action) => sim.tick(&action, MOVE_CHECK_MICROTICKS, level, properties),
+        }
+    }
+    None
+}
+
+pub type DistanceMap = FnvHashMap<(TilePos, VerticalState), i32>;
+
+/// Граф ходов: исходящие и входящие ходы для каждой вершины (клетка, состояние по вертикали).
+/// Строится по мере надобности от вершин, где оказываются игроки.
+pub struct Paths {
+    pub outgoing: FnvHashMap<(TilePos, VerticalState), Vec<Move>>,
+    pub incoming: FnvHashMap<(TilePos, VerticalState), Vec<Move>>,
+}
+
+impl Default for Paths {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Paths {
+    pub fn new() -> Self {
+        Paths {
+            outgoing: FnvHashMap::default(),
+            incoming: FnvHashMap::default(),
+        }
+    }
+
+    /// достроить граф всеми вершинами, достижимыми из pos
+    pub fn update_paths(&mut self, pos: TilePos, vertical_state: VerticalState, level: &Level, properties: &Properties) {
+        let now = Instant::now();
+        let mut queue = VecDeque::new();
+        queue.push_back(make_start_node(pos, vertical_state));
+        let mut count_o = 0;
+        let mut count_i = 0;
+        while !queue.is_empty() {
+            let mov = queue.pop_front().unwrap();
+            if self.outgoing.contains_key(&(mov.pos2, mov.vertical_state2)) {
+                continue;
+            }
+            self.outgoing.insert((mov.pos2, mov.vertical_state2), Vec::new());
+            for movement in get_movements() {
+                if let Some(mov2) = movement.can_move(mov.pos2, mov.vertical_state2, level, properties) {
+                    let outgoing_entry = self.outgoing.entry((mov2.pos1, mov2.vertical_state1)).or_default();
+                    if !outgoing_entry.contains(&mov2) {
+                        outgoing_entry.push(mov2.clone());
+                        count_o += 1;
+                    }
+                    let incoming_entry = self.incoming.entry((mov2.pos2, mov2.vertical_state2)).or_default();
+                    if !incoming_entry.contains(&mov2) {
+                        incoming_entry.push(mov2.clone());
+                        count_i += 1;
+                    }
+                    if !self.outgoing.contains_key(&(mov2.pos2, mov2.vertical_state2)) {
+                        queue.push_back(mov2);
+                    }
+                }
+            }
+        }
+        println!("outgoing {} incoming {} count_o {} count_i {}, {} ms", self.outgoing.len(), self.incoming.len(), count_o, count_i, now.elapsed().as_millis());
+    }
+
+    /// расстояния от всех вершин до клетки target_pos (по входящим ходам)
+    pub fn build_all_paths(&self, target_pos: TilePos) -> DistanceMap {
+        let mut map: DistanceMap = FnvHashMap::default();
+        let mut queue = VecDeque::new();
+        self.incoming
+            .keys()
+            .filter(|(pos, _vertical_state)| *pos == target_pos)
+            .for_each(|(pos, vertical_state)| queue.push_back((*pos, *vertical_state, 0)));
+        let empty_vec = vec![];
+        while !queue.is_empty() {
+            let (pos, vertical_state, ticks) = queue.pop_front().unwrap();
+            match map.entry((pos, vertical_state)) {
+                Vacant(e) => {
+                    e.insert(ticks);
+                }
+                Occupied(mut e) => {
+                    if *e.get() > ticks { e.insert(ticks); } else { continue; }
+                }
+            };
+            self.incoming.get(&(pos, vertical_state)).unwrap_or(&empty_vec)
+                .iter()
+                .for_each(|mov| queue.push_back((mov.pos1, mov.vertical_state1, ticks + mov.ticks)));
+        }
+        map
+    }
+
+    /// расстояния от стартовой вершины до всех достижимых (по исходящим ходам)
+    pub fn build_paths_from(&self, start_pos: TilePos, start_vertical_state: VerticalState) -> DistanceMap {
+        let mut map: DistanceMap = FnvHashMap::default();
+        let mut queue = VecDeque::new();
+        queue.push_back((start_pos, start_vertical_state, 0));
+        let empty_vec = vec![];
+        while !queue.is_empty() {
+            let (pos, vertical_state, ticks) = queue.pop_front().unwrap();
+            match map.entry((pos, vertical_state)) {
+                Vacant(e) => {
+                    e.insert(ticks);
+                }
+                Occupied(mut e) => {
+                    if *e.get() > ticks { e.insert(ticks); } else { continue; }
+                }
+            };
+            self.outgoing.get(&(pos, vertical_state)).unwrap_or(&empty_vec)
+                .iter()
+                .for_each(|mov| queue.push_back((mov.pos2, mov.vertical_state2, ticks + mov.ticks)));
+        }
+        map
+    }
+}
+
+/// вершина начала пути: ход на месте
+pub fn make_start_node(pos: TilePos, vertical_state: VerticalState) -> Move {
+    Move {
+        typ: MoveType::Start,
+        pos1: pos,
+        vertical_state1: vertical_state,
+        pos2: pos,
+        vertical_state2: vertical_state,
+        ticks: 0,
+        control: Rc::new(|_, _| ControlResult::TargetReached),
+    }
+}
+
 /// метод, чтобы в случае ошибки движения вернуться к какому-нибудь квадрату, из которого можно будет построить новый маршрут
 pub fn get_recover_move() -> Move {
     Move {
@@ -959,7 +1334,7 @@ pub fn get_mine_suicide_move() -> Move {
 }
 
 pub fn get_movements() -> Vec<Box<dyn TileMovement>> {
-    vec![
+    let mut movements: Vec<Box<dyn TileMovement>> = vec![
         Box::new(WalkSideMovement { delta: -1 }), // left
         Box::new(WalkSideMovement { delta: 1 }), // right
         Box::new(LadderMovement { vdelta: 1 }), // up
@@ -985,7 +1360,17 @@ pub fn get_movements() -> Vec<Box<dyn TileMovement>> {
         Box::new(PadJump2Movement { delta: 1 }), // up right
         Box::new(PadJumpUpMovement {}), // up
         Box::new(PadJumpStopMovement {}),
-    ]
+        Box::new(PlatformDropMovement { delta: -1 }), // down left
+        Box::new(PlatformDropMovement { delta: 0 }), // down
+        Box::new(PlatformDropMovement { delta: 1 }), // down right
+    ];
+    // дальние прыжки через 2-4 клетки: выше, на том же уровне и ниже
+    for delta in &[-4, -3, -2, 2, 3, 4] {
+        for delta_y in &[-2, -1, 0, 1] {
+            movements.push(Box::new(JumpArcMovement { delta: *delta, delta_y: *delta_y }));
+        }
+    }
+    movements
 }
 
 fn check_possible_location(pos: TilePos, level: &Level, avoid_ladders: bool, avoid_jump_pad: bool) -> bool {
@@ -1023,4 +1408,34 @@ fn unit_is_on_ladder(pos: TilePos, level: &Level) -> bool {
         _ => {}
     };
     false
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::test_utils;
+
+    use super::*;
+
+    #[test]
+    fn keep_speed_only_into_walk_and_fall() {
+        let level = test_utils::level(&[
+            "##########",
+            "#........#",
+            "#........#",
+            "#........#",
+            "#........#",
+            "#....#####",
+            "##########",
+        ]);
+        let mut paths = Paths::new();
+        paths.update_paths((1, 1), VerticalState::Default, &level, &test_utils::properties());
+        let outgoing = |pos: TilePos, typ: MoveType| paths.outgoing[&(pos, VerticalState::Default)].iter()
+            .find(|mov| mov.typ == typ)
+            .unwrap()
+            .clone();
+        let walk = outgoing((1, 1), MoveType::WalkRight);
+        assert!(can_keep_speed(&walk, &outgoing((2, 1), MoveType::WalkRight)));
+        assert!(!can_keep_speed(&walk, &outgoing((2, 1), MoveType::WalkLeft)));
+        assert!(!can_keep_speed(&walk, &outgoing((2, 1), MoveType::JumpRight)));
+        assert!(!can_keep_speed(&walk, &outgoing((2, 1), MoveType::JumpArcRight)));
+    }
+}
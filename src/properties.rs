@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+
+use model::{BulletParams, ExplosionParams, Properties, Vec2F64, WeaponParams, WeaponType};
+
+/// параметры игры по правилам чемпионата
+pub fn default_properties() -> Properties {
+    let mut weapon_params = HashMap::new();
+    weapon_params.insert(WeaponType::Pistol, WeaponParams {
+        magazine_size: 8, fire_rate: 0.4, reload_time: 1.0, min_spread: 0.05, max_spread: 0.5, recoil: 0.5, aim_speed: 1.0,
+        bullet: BulletParams { speed: 50.0, size: 0.2, damage: 20 },
+        explosion: None,
+    });
+    weapon_params.insert(WeaponType::AssaultRifle, WeaponParams {
+        magazine_size: 20, fire_rate: 0.1, reload_time: 1.0, min_spread: 0.1, max_spread: 0.5, recoil: 0.2, aim_speed: 1.9,
+        bullet: BulletParams { speed: 50.0, size: 0.2, damage: 5 },
+        explosion: None,
+    });
+    weapon_params.insert(WeaponType::RocketLauncher, WeaponParams {
+        magazine_size: 1, fire_rate: 1.0, reload_time: 1.0, min_spread: 0.1, max_spread: 0.5, recoil: 1.0, aim_speed: 1.0,
+        bullet: BulletParams { speed: 20.0, size: 0.4, damage: 30 },
+        explosion: Some(ExplosionParams { radius: 3.0, damage: 50 }),
+    });
+    Properties {
+        max_tick_count: 3600,
+        team_size: 2,
+        ticks_per_second: 60.0,
+        updates_per_tick: 100,
+        loot_box_size: Vec2F64 { x: 0.5, y: 0.5 },
+        unit_size: Vec2F64 { x: 0.9, y: 1.8 },
+        unit_max_horizontal_speed: 10.0,
+        unit_fall_speed: 10.0,
+        unit_jump_time: 0.55,
+        unit_jump_speed: 10.0,
+        jump_pad_jump_time: 0.525,
+        jump_pad_jump_speed: 20.0,
+        unit_max_health: 100,
+        health_pack_health: 50,
+        weapon_params,
+        mine_size: Vec2F64 { x: 0.5, y: 0.5 },
+        mine_explosion_params: ExplosionParams { radius: 3.0, damage: 50 },
+        mine_prepare_time: 1.0,
+        mine_trigger_time: 0.5,
+        mine_trigger_radius: 1.0,
+        kill_score: 1000,
+    }
+}
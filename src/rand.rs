@@ -2,18 +2,29 @@
 
 use core::mem;
 
+#[derive(Debug)]
 pub struct Random {
     seed: u64,
+    next_next_gaussian: Option<f64>,
 }
 
 const MULTIPLIER: u64 = 0x5DEECE66D;
 const ADDEND: u64 = 0xB;
 const MASK: u64 = (1 << 48) - 1;
+// шаг splitmix64 для зерен подпотоков
+const GOLDEN_GAMMA: u64 = 0x9E3779B97F4A7C15;
+
+// финализатор splitmix64: близкие входы дают независимые зерна
+fn mix64(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
 
 #[allow(dead_code)]
 impl Random {
     pub fn new(seed: u64) -> Self {
-        Self { seed: (seed ^ MULTIPLIER) & MASK }
+        Self { seed: (seed ^ MULTIPLIER) & MASK, next_next_gaussian: None }
     }
 
     pub fn next(&mut self, bits: u32) -> u32 {
@@ -54,4 +65,106 @@ impl Random {
             }
         }
     }
-}
\ No newline at end of file
+
+    /// Нормальное распределение N(0, 1), как java.lang.Random.nextGaussian (полярный метод, второе значение кешируется)
+    pub fn next_gaussian(&mut self) -> f64 {
+        if let Some(gaussian) = self.next_next_gaussian.take() {
+            return gaussian;
+        }
+        loop {
+            let v1 = 2.0 * self.next_f64() - 1.0;
+            let v2 = 2.0 * self.next_f64() - 1.0;
+            let s = v1 * v1 + v2 * v2;
+            if s < 1.0 && s != 0.0 {
+                let multiplier = (-2.0 * s.ln() / s).sqrt();
+                self.next_next_gaussian = Some(v2 * multiplier);
+                return v1 * multiplier;
+            }
+        }
+    }
+
+    /// Индекс с вероятностью, пропорциональной весу; отрицательные веса считаются нулевыми.
+    /// None, если положительных весов нет.
+    pub fn choose_weighted(&mut self, weights: &[f64]) -> Option<usize> {
+        let total: f64 = weights.iter().filter(|weight| **weight > 0.0).sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut value = self.next_f64() * total;
+        let mut last = None;
+        for (index, weight) in weights.iter().enumerate() {
+            if *weight <= 0.0 {
+                continue;
+            }
+            if value < *weight {
+                return Some(index);
+            }
+            value -= weight;
+            last = Some(index);
+        }
+        // погрешность суммирования
+        last
+    }
+
+    /// Перемешивание Фишера-Йетса
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.next_u32_bounded(i as u32 + 1) as usize;
+            items.swap(i, j);
+        }
+    }
+
+    /// Новый независимый генератор; этот при этом сдвигается
+    pub fn split(&mut self) -> Random {
+        Random::new(mix64(self.next_u64()))
+    }
+
+    /// Подпоток с номером index (например, id игрока): зависит только от текущего состояния и index,
+    /// этот генератор не сдвигается, так что потоки не влияют друг на друга.
+    pub fn sub_stream(&self, index: u64) -> Random {
+        Random::new(mix64(self.seed.wrapping_add(index.wrapping_add(1).wrapping_mul(GOLDEN_GAMMA))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Random;
+
+    fn sequence(rand: &mut Random) -> Vec<u64> {
+        (0..8).map(|_| rand.next_u64()).collect()
+    }
+
+    #[test]
+    fn choose_weighted_skips_non_positive_weights() {
+        let mut rand = Random::new(42);
+        for _ in 0..1000 {
+            let index = rand.choose_weighted(&[0.0, 1.0, -5.0, 2.0, 0.0]).unwrap();
+            assert!(index == 1 || index == 3, "index {}", index);
+        }
+        assert_eq!(rand.choose_weighted(&[0.0, -1.0]), None);
+        assert_eq!(rand.choose_weighted(&[]), None);
+    }
+
+    #[test]
+    fn shuffle_is_permutation() {
+        let mut rand = Random::new(42);
+        let mut items: Vec<usize> = (0..50).collect();
+        rand.shuffle(&mut items);
+        assert_ne!(items, (0..50).collect::<Vec<_>>());
+        items.sort();
+        assert_eq!(items, (0..50).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn streams_depend_only_on_seed_and_index() {
+        let rand = Random::new(42);
+        assert_eq!(sequence(&mut rand.sub_stream(1)), sequence(&mut Random::new(42).sub_stream(1)));
+        assert_ne!(sequence(&mut rand.sub_stream(1)), sequence(&mut rand.sub_stream(2)));
+
+        let (mut rand1, mut rand2) = (Random::new(42), Random::new(42));
+        let (mut split1, mut split2) = (rand1.split(), rand2.split());
+        assert_eq!(sequence(&mut split1), sequence(&mut split2));
+        // split сдвигает генератор: следующий поток уже другой
+        assert_ne!(sequence(&mut rand1.split()), sequence(&mut Random::new(42).split()));
+    }
+}
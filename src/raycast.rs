@@ -0,0 +1,180 @@
+use model::{Level, Tile, Vec2F64};
+
+const EPSILON: f64 = 1e-9;
+
+/// Первое касание движущимся квадратом (пулей) стены или границы мира.
+/// Проход по клеткам (DDA) по переднему краю квадрата: проверяются только клетки, в которые квадрат входит.
+/// velocity - смещение за единицу времени, возвращает точку и время касания, если оно не позже max_time.
+pub fn wall_hit(position: Vec2F64, velocity: Vec2F64, half_size: f64, max_time: f64, level: &Level) -> Option<(Vec2F64, f64)> {
+    let is_wall = |x: isize, y: isize| {
+        x < 0 || y < 0 || x >= level.width() as isize || y >= level.height() as isize ||
+            level.tiles[x as usize][y as usize] == Tile::Wall
+    };
+
+    // уже касаемся стены со стороны движения
+    let (x0, x1) = overlapped(position.x - half_size, position.x + half_size, velocity.x);
+    let (y0, y1) = overlapped(position.y - half_size, position.y + half_size, velocity.y);
+    if (x0..=x1).any(|x| (y0..=y1).any(|y| is_wall(x, y))) {
+        return Some((position, 0.0));
+    }
+    if velocity.x == 0.0 && velocity.y == 0.0 {
+        return None;
+    }
+
+    // следующая вертикальная граница клеток, которую пересечет передний край, и номер новой колонки
+    let (mut column, mut t_x, t_delta_x, step_x) = if velocity.x > 0.0 {
+        let edge = position.x + half_size;
+        (edge.ceil() as isize, (edge.ceil() - edge) / velocity.x, 1.0 / velocity.x, 1)
+    } else if velocity.x < 0.0 {
+        let edge = position.x - half_size;
+        (edge.floor() as isize - 1, (edge.floor() - edge) / velocity.x, -1.0 / velocity.x, -1)
+    } else {
+        (0, f64::INFINITY, f64::INFINITY, 0)
+    };
+    let (mut row, mut t_y, t_delta_y, step_y) = if velocity.y > 0.0 {
+        let edge = position.y + half_size;
+        (edge.ceil() as isize, (edge.ceil() - edge) / velocity.y, 1.0 / velocity.y, 1)
+    } else if velocity.y < 0.0 {
+        let edge = position.y - half_size;
+        (edge.floor() as isize - 1, (edge.floor() - edge) / velocity.y, -1.0 / velocity.y, -1)
+    } else {
+        (0, f64::INFINITY, f64::INFINITY, 0)
+    };
+
+    loop {
+        let t = t_x.min(t_y);
+        if t > max_time {
+            return None;
+        }
+        let p = position.add(velocity.mul(t));
+        if t_x <= t_y {
+            // передний край вошел в новую колонку
+            let (y0, y1) = overlapped(p.y - half_size, p.y + half_size, velocity.y);
+            if (y0..=y1).any(|y| is_wall(column, y)) {
+                return Some((p, t));
+            }
+            column += step_x;
+            t_x += t_delta_x;
+        } else {
+            // передний край вошел в новую строку
+            let (x0, x1) = overlapped(p.x - half_size, p.x + half_size, velocity.x);
+            if (x0..=x1).any(|x| is_wall(x, row)) {
+                return Some((p, t));
+            }
+            row += step_y;
+            t_y += t_delta_y;
+        }
+    }
+}
+
+/// клетки, которые занимает отрезок [lo, hi] по одной оси, касание со стороны движения тоже считается
+fn overlapped(lo: f64, hi: f64, velocity: f64) -> (isize, isize) {
+    let from = if velocity < 0.0 { (lo - EPSILON).floor() } else { (lo + EPSILON).floor() };
+    let to = if velocity > 0.0 { hi.floor() } else { (hi - EPSILON).floor() };
+    (from as isize, to as isize)
+}
+
+#[cfg(test)]
+mod tests {
+    use model::{Level, Properties, Tile, Vec2F64, WeaponType};
+
+    use crate::rand::Random;
+    use crate::test_utils;
+
+    use super::*;
+
+    // старый расчет конца полета пули шагами по 1/100 тика
+    fn bullet_end_marching(position: Vec2F64, aim: Vec2F64, weapon_type: &WeaponType, level: &Level, properties: &Properties) -> (Vec2F64, f64) {
+        let weapon_params = &properties.weapon_params[weapon_type];
+        let speed = {
+            let aim_length = ((aim.x).powi(2) + (aim.y).powi(2)).sqrt();
+            Vec2F64 { x: aim.x * weapon_params.bullet.speed / aim_length, y: aim.y * weapon_params.bullet.speed / aim_length }
+        };
+        let half_bullet_size = weapon_params.bullet.size / 2.0;
+        let mut position2 = position;
+        let time_step = 1.0 / properties.ticks_per_second / 100.0;// 100 microticks
+        loop {
+            position2 = position2.add(speed.mul(time_step));
+            if wall_collision(Vec2F64 { x: position2.x - half_bullet_size, y: position2.y - half_bullet_size }, level) ||
+                wall_collision(Vec2F64 { x: position2.x + half_bullet_size, y: position2.y - half_bullet_size }, level) ||
+                wall_collision(Vec2F64 { x: position2.x - half_bullet_size, y: position2.y + half_bullet_size }, level) ||
+                wall_collision(Vec2F64 { x: position2.x + half_bullet_size, y: position2.y + half_bullet_size }, level) {
+                let len = ((position.x - position2.x).powi(2) + (position.y - position2.y).powi(2)).sqrt();
+                let tick = len / weapon_params.bullet.speed * properties.ticks_per_second;
+                return (position2, tick);
+            }
+        }
+    }
+
+    fn wall_collision(pos: Vec2F64, level: &Level) -> bool {
+        if pos.x <= 0.0 || pos.x >= level.width() as f64 || pos.y <= 0.0 || pos.y >= level.height() as f64 {
+            return true;
+        }
+        level.tiles[pos.x as usize][pos.y as usize] == Tile::Wall
+    }
+
+    /// Сверка wall_hit с пошаговым расчетом на случайных выстрелах из свободных клеток.
+    /// Точный расчет не может найти столкновение позже пошагового, а раньше - только когда угол стены
+    /// попадает в сторону пули между ее углами (пошаговый расчет проверяет только углы).
+    #[test]
+    fn wall_hit_matches_marching() {
+        let level = test_utils::level(&[
+            "####################",
+            "#..................#",
+            "#....###......#....#",
+            "#..........H..#....#",
+            "#..^^^^....H.......#",
+            "#..........H...##..#",
+            "#.#....#...H.......#",
+            "#.#....#....^^^^...#",
+            "#......#...........#",
+            "####################",
+        ]);
+        let properties = test_utils::properties();
+        let mut rand = Random::new(98754);
+        let time_step = 1.0 / 100.0;
+        let weapon_types = [WeaponType::Pistol, WeaponType::AssaultRifle, WeaponType::RocketLauncher];
+        let mut shots = 0;
+        let mut mismatches = 0;
+        while shots < 2000 {
+            let position = Vec2F64 {
+                x: rand.next_f64() * level.width() as f64,
+                y: rand.next_f64() * level.height() as f64,
+            };
+            let weapon_type = &weapon_types[rand.next_u32_bounded(weapon_types.len() as u32) as usize];
+            let weapon_params = &properties.weapon_params[weapon_type];
+            let half_size = weapon_params.bullet.size / 2.0;
+            if wall_hit(position, Vec2F64 { x: 0.0, y: 0.0 }, half_size, 0.0, &level).is_some() {
+                // внутри стены не стреляем
+                continue;
+            }
+            shots += 1;
+            let angle = rand.next_f64() * 2.0 * std::f64::consts::PI;
+            let aim = Vec2F64 { x: angle.cos(), y: angle.sin() };
+            let (_, marching_tick) = bullet_end_marching(position, aim, weapon_type, &level, &properties);
+            let velocity = aim.mul(weapon_params.bullet.speed / properties.ticks_per_second);
+            let (_, tick) = wall_hit(position, velocity, half_size, f64::INFINITY, &level).unwrap();
+            assert!(tick <= marching_tick + EPSILON, "wall_hit {} after marching {} from {:?} aim {:?}", tick, marching_tick, position, aim);
+            if marching_tick - tick > time_step + EPSILON {
+                mismatches += 1;
+            }
+        }
+        // расхождения только на углах стен, их немного
+        assert!(mismatches * 20 < shots, "{} mismatches of {} shots", mismatches, shots);
+    }
+
+    #[test]
+    fn wall_hit_respects_max_time() {
+        let level = test_utils::level(&[
+            "######",
+            "#....#",
+            "######",
+        ]);
+        let position = Vec2F64 { x: 1.5, y: 1.5 };
+        let velocity = Vec2F64 { x: 1.0, y: 0.0 };
+        let (point, time) = wall_hit(position, velocity, 0.1, 10.0, &level).unwrap();
+        assert!((time - 3.4).abs() < EPSILON);
+        assert!((point.x - 4.9).abs() < EPSILON);
+        assert!(wall_hit(position, velocity, 0.1, 3.0, &level).is_none());
+    }
+}
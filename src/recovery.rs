@@ -0,0 +1,143 @@
+use std::rc::Rc;
+
+use model::{Game, Level, Tile, Unit, Vec2F64};
+
+use crate::path::{choose_horizontal_speed, target_reached, ControlResult, Move, MoveAction, MoveType, TilePos, VerticalState};
+use crate::unit_sim::UnitSim;
+
+// запас к размеру игрока, в пределах которого другой игрок мешает идти
+const BLOCK_MARGIN: f64 = 0.1;
+// где ищем клетку графа для возврата: по горизонтали в обе стороны, вниз и вверх от игрока
+const SEARCH_X: isize = 2;
+const SEARCH_DOWN: isize = 3;
+const SEARCH_UP: isize = 2;
+// сколько тиков даем на возврат
+const RECOVERY_TICKS: i32 = 40;
+const RECOVERY_MICROTICKS: usize = 5;
+
+/// Почему ход не удался
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Failure {
+    // уперлись в другого игрока
+    BlockedByUnit,
+    // сорвались с лестницы
+    FellOffLadder,
+    // батут подбросил не туда
+    JumpPadOvershoot,
+    // стоим, упершись в стену
+    StuckAtWall,
+    // просто ушли с хода (неточная модель хода)
+    OffPath,
+}
+
+/// Классификация неудачи хода mov по фактическому состоянию игрока, stuck - положение не изменилось за тик
+pub fn classify_failure(mov: &Move, unit: &Unit, stuck: bool, game: &Game) -> Failure {
+    let level = &game.level;
+    let direction = (mov.pos2.0 - mov.pos1.0).signum() as f64;
+    let blocked = game.units.iter().any(|unit2| unit2.id != unit.id &&
+        (unit2.position.x - unit.position.x).abs() < (unit.size.x + unit2.size.x) / 2.0 + BLOCK_MARGIN &&
+        (unit2.position.y - unit.position.y).abs() < (unit.size.y + unit2.size.y) / 2.0 + BLOCK_MARGIN &&
+        (unit2.position.x - unit.position.x) * direction >= 0.0);
+    if blocked {
+        return Failure::BlockedByUnit;
+    }
+    if matches!(mov.typ, MoveType::LadderUp | MoveType::LadderDown) && !on_ladder(unit.position, unit.size, level) {
+        return Failure::FellOffLadder;
+    }
+    let pad_jump = unit.jump_state.can_jump && !unit.jump_state.can_cancel;
+    if pad_jump || matches!(mov.vertical_state1, VerticalState::PadJump(_)) || matches!(mov.vertical_state2, VerticalState::PadJump(_)) {
+        return Failure::JumpPadOvershoot;
+    }
+    if stuck {
+        // стена вплотную сбоку или над головой
+        let x = unit.position.x + (unit.size.x / 2.0 + BLOCK_MARGIN) * if direction != 0.0 { direction } else { 1.0 };
+        let side = [0.1, unit.size.y / 2.0, unit.size.y - 0.1].iter().any(|dy| tile(x, unit.position.y + dy, level) == Tile::Wall) ||
+            (direction == 0.0 && [0.1, unit.size.y / 2.0, unit.size.y - 0.1].iter()
+                .any(|dy| tile(unit.position.x - unit.size.x / 2.0 - BLOCK_MARGIN, unit.position.y + dy, level) == Tile::Wall));
+        let above = tile(unit.position.x, unit.position.y + unit.size.y + BLOCK_MARGIN, level) == Tile::Wall;
+        if side || above {
+            return Failure::StuckAtWall;
+        }
+    }
+    Failure::OffPath
+}
+
+/// Возврат на граф из фактического состояния (положение и прыжок): перебираем клетки графа рядом
+/// и способы до них добраться (идти, прыгать, пока ниже цели, еще и спрыгивать, пока выше),
+/// прогоняем в модели движения и берем самый быстрый. is_node - есть ли в графе клетка в обычном состоянии.
+pub fn plan_recovery(unit: &Unit, game: &Game, is_node: impl Fn(TilePos) -> bool) -> Option<Move> {
+    let level = &game.level;
+    let properties = &game.properties;
+    let pos = (unit.position.x as isize, unit.position.y as isize);
+    let mut best: Option<(TilePos, Policy, i32)> = None;
+    for x in pos.0 - SEARCH_X..=pos.0 + SEARCH_X {
+        for y in pos.1 - SEARCH_DOWN..=pos.1 + SEARCH_UP {
+            let target = (x, y);
+            if x < 0 || y < 0 || x >= level.width() as isize || y >= level.height() as isize || !is_node(target) {
+                continue;
+            }
+            for policy in &[Policy { jump: false, jump_down: false }, Policy { jump: true, jump_down: false }, Policy { jump: true, jump_down: true }] {
+                let max_ticks = best.map(|(_, _, ticks)| ticks).unwrap_or(RECOVERY_TICKS);
+                let mut sim = UnitSim::new(unit);
+                for tick in 0..max_ticks {
+                    let vertical_state = sim.vertical_state(properties);
+                    if vertical_state == VerticalState::Default && target_reached(sim.position, target, vertical_state) {
+                        best = Some((target, *policy, tick));
+                        break;
+                    }
+                    sim.tick(&policy.action(sim.position, target), RECOVERY_MICROTICKS, level, properties);
+                }
+            }
+        }
+    }
+    best.map(|(target, policy, ticks)| Move {
+        typ: MoveType::Recover,
+        pos1: pos,
+        vertical_state1: crate::unit_sim::vertical_state(&unit.jump_state, properties),
+        pos2: target,
+        vertical_state2: VerticalState::Default,
+        ticks: ticks.max(1),
+        control: Rc::new(move |position: Vec2F64, vertical_state: VerticalState| {
+            if vertical_state == VerticalState::Default && target_reached(position, target, vertical_state) {
+                return ControlResult::TargetReached;
+            }
+            let pos = (position.x as isize, position.y as isize);
+            if (pos.0 - target.0).abs() > SEARCH_X + 1 || pos.1 < target.1 - 1 || pos.1 > target.1 + SEARCH_DOWN + 1 {
+                return ControlResult::Recover;
+            }
+            ControlResult::MoveAction(policy.action(position, target))
+        }),
+    })
+}
+
+/// способ добраться до клетки: к центру по горизонтали, по вертикали - как разрешено
+#[derive(Clone, Copy, Debug)]
+struct Policy {
+    // прыгать, пока ниже цели
+    jump: bool,
+    // спрыгивать с платформ, пока выше цели
+    jump_down: bool,
+}
+
+impl Policy {
+    fn action(&self, position: Vec2F64, target: TilePos) -> MoveAction {
+        MoveAction {
+            typ: MoveType::Recover,
+            velocity: choose_horizontal_speed(position.x, target.0 as f64 + 0.5),
+            jump: self.jump && position.y < target.1 as f64,
+            jump_down: self.jump_down && position.y >= target.1 as f64 + 1.0,
+        }
+    }
+}
+
+fn tile(x: f64, y: f64, level: &Level) -> Tile {
+    if x < 0.0 || y < 0.0 || x >= level.width() as f64 || y >= level.height() as f64 {
+        Tile::Wall
+    } else {
+        level.tiles[x as usize][y as usize].clone()
+    }
+}
+
+fn on_ladder(position: Vec2F64, size: Vec2F64, level: &Level) -> bool {
+    tile(position.x, position.y, level) == Tile::Ladder || tile(position.x, position.y + size.y / 2.0, level) == Tile::Ladder
+}
@@ -0,0 +1,62 @@
+use model::{Game, JumpState, Level, Properties, Tile, Unit, Vec2F64, Weapon, WeaponType};
+
+pub use crate::properties::default_properties as properties;
+
+/// Уровень из текста, строки сверху вниз: `#` стена, `^` платформа, `H` лестница, `T` батут, остальное пусто
+pub fn level(rows: &[&str]) -> Level {
+    let height = rows.len();
+    let width = rows[0].len();
+    let mut tiles = vec![vec![Tile::Empty; height]; width];
+    for (i, row) in rows.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            tiles[x][height - 1 - i] = match c {
+                '#' => Tile::Wall,
+                '^' => Tile::Platform,
+                'H' => Tile::Ladder,
+                'T' => Tile::JumpPad,
+                _ => Tile::Empty,
+            };
+        }
+    }
+    Level { tiles }
+}
+
+/// игрок стоит на земле в точке (x, y) (середина низа)
+pub fn unit(id: i32, player_id: i32, x: f64, y: f64, weapon_type: Option<WeaponType>, properties: &Properties) -> Unit {
+    Unit {
+        player_id,
+        id,
+        health: properties.unit_max_health,
+        position: Vec2F64 { x, y },
+        size: properties.unit_size,
+        jump_state: JumpState { can_jump: true, speed: properties.unit_jump_speed, max_time: properties.unit_jump_time, can_cancel: true },
+        walked_right: false,
+        stand: true,
+        on_ground: true,
+        on_ladder: false,
+        mines: 0,
+        weapon: weapon_type.map(|typ| Weapon {
+            params: properties.weapon_params[&typ].clone(),
+            magazine: properties.weapon_params[&typ].magazine_size,
+            spread: properties.weapon_params[&typ].min_spread,
+            typ,
+            was_shooting: false,
+            fire_timer: None,
+            last_angle: None,
+            last_fire_tick: None,
+        }),
+    }
+}
+
+pub fn game(level: Level, units: Vec<Unit>) -> Game {
+    Game {
+        current_tick: 0,
+        properties: properties(),
+        level,
+        players: Vec::new(),
+        units,
+        bullets: Vec::new(),
+        mines: Vec::new(),
+        loot_boxes: Vec::new(),
+    }
+}
@@ -0,0 +1,220 @@
+use model::{JumpState, Level, Properties, Tile, Unit, Vec2F64};
+
+use crate::path::{ControlResult, Move, MoveAction, MoveType, smooth_control, VerticalState};
+
+const EPSILON: f64 = 1e-9;
+// во сколько раз ход может затянуться по сравнению с расчетным, прежде чем считаем, что план сорвался
+const MOVE_TICKS_FACTOR: i32 = 2;
+
+/// Модель движения игрока по правилам сервера с шагом в микротик: ходьба со столкновением со стенами,
+/// прыжок (с отменой), падение с приземлением на стены и платформы, лестницы и батуты.
+/// Столкновения с другими игроками не учитываются.
+#[derive(Clone, Debug)]
+pub struct UnitSim {
+    pub position: Vec2F64,
+    pub size: Vec2F64,
+    pub jump_state: JumpState,
+}
+
+impl UnitSim {
+    pub fn new(unit: &Unit) -> Self {
+        Self {
+            position: unit.position,
+            size: unit.size,
+            jump_state: unit.jump_state.clone(),
+        }
+    }
+
+    /// Состояние по вертикали для управления ходами графа (как в get_vertical_state)
+    pub fn vertical_state(&self, properties: &Properties) -> VerticalState {
+        vertical_state(&self.jump_state, properties)
+    }
+
+    /// Прогон произвольной последовательности действий, по одному на тик.
+    /// Возвращает положения на начало каждого тика и после последнего (actions.len() + 1 точек).
+    pub fn rollout(&mut self, actions: &[MoveAction], microticks: usize, level: &Level, properties: &Properties) -> Vec<Vec2F64> {
+        let mut positions = vec![self.position];
+        for action in actions {
+            self.tick(action, microticks, level, properties);
+            positions.push(self.position);
+        }
+        positions
+    }
+
+    /// Прогон плана: ходы path начиная с from_index выполняются их же управлением со сглаживанием, как в игре.
+    /// Когда план кончился или сорвался (Recover, ход затянулся) - стоим.
+    /// Возвращает положения на тики 0..=ticks.
+    pub fn follow_path(&mut self, path: &[Move], from_index: usize, ticks: i32, microticks: usize,
+                       level: &Level, properties: &Properties) -> Vec<Vec2F64> {
+        let stand = MoveAction { typ: MoveType::Start, velocity: 0.0, jump: false, jump_down: false };
+        let mut positions = vec![self.position];
+        let mut index = from_index;
+        let mut move_ticks = 0;
+        while positions.len() <= ticks as usize {
+            let action = loop {
+                let mov = match path.get(index) {
+                    Some(mov) => mov,
+                    None => break None,
+                };
+                match smooth_control(mov, path.get(index + 1), self.position, self.vertical_state(properties)) {
+                    ControlResult::TargetReached => {
+                        index += 1;
+                        move_ticks = 0;
+                    }
+                    ControlResult::Recover => {
+                        index = path.len();
+                        break None;
+                    }
+                    ControlResult::MoveAction(action) => {
+                        if move_ticks > mov.ticks * MOVE_TICKS_FACTOR {
+                            index = path.len();
+                            break None;
+                        }
+                        break Some(action);
+                    }
+                }
+            };
+            move_ticks += 1;
+            self.tick(action.as_ref().unwrap_or(&stand), microticks, level, properties);
+            positions.push(self.position);
+        }
+        positions
+    }
+
+    /// один тик с действием action, разбитый на microticks шагов
+    pub fn tick(&mut self, action: &MoveAction, microticks: usize, level: &Level, properties: &Properties) {
+        let dt = 1.0 / properties.ticks_per_second / microticks as f64;
+        for _ in 0..microticks {
+            self.step(action, dt, level, properties);
+        }
+    }
+
+    fn step(&mut self, action: &MoveAction, dt: f64, level: &Level, properties: &Properties) {
+        let half_width = self.size.x / 2.0;
+
+        // по горизонтали - до стены
+        let velocity = action.velocity.max(-properties.unit_max_horizontal_speed).min(properties.unit_max_horizontal_speed);
+        let x = self.position.x + velocity * dt;
+        if !self.hits_wall(x, self.position.y, level) {
+            self.position.x = x;
+        } else if velocity > 0.0 {
+            self.position.x = (x + half_width).floor() - half_width;
+        } else if velocity < 0.0 {
+            self.position.x = (x - half_width).ceil() + half_width;
+        }
+
+        if self.touches(Tile::JumpPad, level) {
+            self.jump_state = JumpState {
+                can_jump: true,
+                speed: properties.jump_pad_jump_speed,
+                max_time: properties.jump_pad_jump_time,
+                can_cancel: false,
+            };
+        }
+
+        let on_ladder = self.on_ladder(level);
+        if on_ladder {
+            self.reset_jump(properties);
+        }
+
+        if self.jump_state.can_jump && self.jump_state.max_time > 0.0 && (action.jump || !self.jump_state.can_cancel) {
+            // прыжок, до потолка
+            let y = self.position.y + self.jump_state.speed * dt;
+            self.jump_state.max_time -= dt;
+            if self.hits_wall(self.position.x, y, level) {
+                self.position.y = (y + self.size.y).floor() - self.size.y;
+                self.stop_jump();
+            } else {
+                self.position.y = y;
+            }
+        } else if on_ladder && !action.jump_down {
+            // на лестнице стоим
+        } else {
+            // падение: приземляемся, если пересекаем верх стены или платформы (с платформы можно спрыгнуть)
+            self.stop_jump();
+            let y = self.position.y - properties.unit_fall_speed * dt;
+            let boundary = self.position.y.floor();
+            if y < boundary + EPSILON && boundary <= self.position.y && self.ground_below(boundary, action.jump_down, level) {
+                self.position.y = boundary;
+                self.reset_jump(properties);
+            } else {
+                self.position.y = y;
+            }
+        }
+    }
+
+    fn reset_jump(&mut self, properties: &Properties) {
+        self.jump_state = JumpState {
+            can_jump: true,
+            speed: properties.unit_jump_speed,
+            max_time: properties.unit_jump_time,
+            can_cancel: true,
+        };
+    }
+
+    fn stop_jump(&mut self) {
+        self.jump_state.can_jump = false;
+        self.jump_state.max_time = 0.0;
+    }
+
+    /// клетки по x, которые занимает игрок
+    fn columns(&self, x: f64) -> (isize, isize) {
+        (((x - self.size.x / 2.0) + EPSILON).floor() as isize, ((x + self.size.x / 2.0) - EPSILON).floor() as isize)
+    }
+
+    fn tile(x: isize, y: isize, level: &Level) -> Tile {
+        if x < 0 || y < 0 || x >= level.width() as isize || y >= level.height() as isize {
+            Tile::Wall
+        } else {
+            level.tiles[x as usize][y as usize].clone()
+        }
+    }
+
+    fn hits_wall(&self, x: f64, y: f64, level: &Level) -> bool {
+        let (x0, x1) = self.columns(x);
+        let (y0, y1) = ((y + EPSILON).floor() as isize, (y + self.size.y - EPSILON).floor() as isize);
+        (x0..=x1).any(|column| (y0..=y1).any(|row| UnitSim::tile(column, row, level) == Tile::Wall))
+    }
+
+    fn touches(&self, tile: Tile, level: &Level) -> bool {
+        let (x0, x1) = self.columns(self.position.x);
+        let (y0, y1) = ((self.position.y + EPSILON).floor() as isize, (self.position.y + self.size.y - EPSILON).floor() as isize);
+        (x0..=x1).any(|column| (y0..=y1).any(|row| UnitSim::tile(column, row, level) == tile))
+    }
+
+    /// лестница под центром или под ногами
+    fn on_ladder(&self, level: &Level) -> bool {
+        let column = self.position.x.floor() as isize;
+        UnitSim::tile(column, self.position.y.floor() as isize, level) == Tile::Ladder ||
+            UnitSim::tile(column, (self.position.y + self.size.y / 2.0).floor() as isize, level) == Tile::Ladder
+    }
+
+    /// можно ли стоять на высоте boundary (верх клетки под ногами)
+    fn ground_below(&self, boundary: f64, jump_down: bool, level: &Level) -> bool {
+        let (x0, x1) = self.columns(self.position.x);
+        let row = boundary as isize - 1;
+        (x0..=x1).any(|column| match UnitSim::tile(column, row, level) {
+            Tile::Wall => true,
+            Tile::Platform => !jump_down,
+            _ => false,
+        })
+    }
+}
+
+/// Состояние по вертикали по состоянию прыжка: обычное (стоим, падаем, прыжок еще не начат) или прыжок с остатком высоты
+pub fn vertical_state(jump_state: &JumpState, properties: &Properties) -> VerticalState {
+    if !jump_state.can_jump || jump_state.max_time == properties.unit_jump_time {
+        VerticalState::Default
+    } else if jump_state.speed == properties.jump_pad_jump_speed {
+        VerticalState::PadJump((jump_state.speed * jump_state.max_time).floor() as usize)
+    } else {
+        VerticalState::Jump((jump_state.speed * jump_state.max_time).floor() as usize)
+    }
+}
+
+/// положения на начало и конец тика tick по результату прогона (после конца - последнее)
+pub fn position_at(positions: &[Vec2F64], tick: i32) -> (Vec2F64, Vec2F64) {
+    let last = positions.len() - 1;
+    let index = (tick.max(0) as usize).min(last);
+    (positions[index], positions[(index + 1).min(last)])
+}
@@ -0,0 +1,120 @@
+use model::{Level, Properties, Tile, Vec2F64, WeaponType};
+
+use crate::fnv_hash::FnvHashMap;
+use crate::path::TilePos;
+use crate::raycast::wall_hit;
+
+/// Карта видимости уровня: какие клетки простреливаются из какой клетки с учетом размера пули.
+/// Строки считаются лениво (по клетке стрелка и типу оружия) и кешируются, уровень не меняется.
+pub struct Visibility {
+    width: usize,
+    height: usize,
+    // клетки, в которых может стоять игрок (ноги в клетке, голова в клетке выше)
+    standing: Vec<bool>,
+    // (клетка стрелка, оружие) -> простреливается ли клетка (индекс x * height + y)
+    exposed: FnvHashMap<(TilePos, WeaponType), Vec<bool>>,
+}
+
+impl Visibility {
+    pub fn new(level: &Level) -> Self {
+        let width = level.width();
+        let height = level.height();
+        let mut standing = vec![false; width * height];
+        for x in 0..width {
+            for y in 0..height - 1 {
+                standing[x * height + y] = level.tiles[x][y] != Tile::Wall && level.tiles[x][y + 1] != Tile::Wall;
+            }
+        }
+        Self {
+            width,
+            height,
+            standing,
+            exposed: FnvHashMap::default(),
+        }
+    }
+
+    /// может ли стрелок из клетки from попасть в игрока, стоящего в клетке pos
+    pub fn is_exposed(&mut self, pos: TilePos, from: TilePos, weapon_type: &WeaponType, level: &Level, properties: &Properties) -> bool {
+        match self.index(pos) {
+            Some(index) => self.row(from, weapon_type, level, properties)[index],
+            None => false,
+        }
+    }
+
+    /// простреливается ли клетка хотя бы одним из противников (клетка, оружие)
+    pub fn is_exposed_to_any(&mut self, pos: TilePos, enemies: &[(TilePos, WeaponType)], level: &Level, properties: &Properties) -> bool {
+        enemies.iter().any(|(from, weapon_type)| self.is_exposed(pos, *from, weapon_type, level, properties))
+    }
+
+    /// ближайшая (по прямой) достижимая клетка, где можно стоять и которая не простреливается никем из противников
+    pub fn nearest_covered_tile(&mut self, pos: TilePos, enemies: &[(TilePos, WeaponType)], is_reachable: impl Fn(TilePos) -> bool,
+                                level: &Level, properties: &Properties) -> Option<TilePos> {
+        let mut best: Option<(TilePos, isize)> = None;
+        for x in 0..self.width as isize {
+            for y in 0..self.height as isize {
+                let pos2 = (x, y);
+                if !self.standing[self.index(pos2).unwrap()] {
+                    continue;
+                }
+                let dist = (x - pos.0).pow(2) + (y - pos.1).pow(2);
+                if let Some((_, best_dist)) = best {
+                    if best_dist <= dist {
+                        continue;
+                    }
+                }
+                if is_reachable(pos2) && !self.is_exposed_to_any(pos2, enemies, level, properties) {
+                    best = Some((pos2, dist));
+                }
+            }
+        }
+        best.map(|(pos2, _)| pos2)
+    }
+
+    /// пролетит ли пуля заданного размера от from до to, не задев стен
+    pub fn segment_clear(from: Vec2F64, to: Vec2F64, bullet_size: f64, level: &Level) -> bool {
+        wall_hit(from, to.sub(from), bullet_size / 2.0, 1.0, level).is_none()
+    }
+
+    /// виден ли стрелку хотя бы один из контрольных точек игрока (центр, ноги, голова, бока)
+    pub fn unit_visible(from: Vec2F64, unit_position: Vec2F64, unit_size: Vec2F64, bullet_size: f64, level: &Level) -> bool {
+        let half_width = unit_size.x / 2.0 - 0.05;
+        let center = unit_position.add(Vec2F64 { x: 0.0, y: unit_size.y / 2.0 });
+        let targets = [
+            center,
+            unit_position.add(Vec2F64 { x: 0.0, y: 0.1 }),
+            unit_position.add(Vec2F64 { x: 0.0, y: unit_size.y - 0.1 }),
+            center.add(Vec2F64 { x: -half_width, y: 0.0 }),
+            center.add(Vec2F64 { x: half_width, y: 0.0 }),
+        ];
+        targets.iter().any(|target| Visibility::segment_clear(from, *target, bullet_size, level))
+    }
+
+    fn row(&mut self, from: TilePos, weapon_type: &WeaponType, level: &Level, properties: &Properties) -> &Vec<bool> {
+        let (width, height) = (self.width, self.height);
+        let standing = &self.standing;
+        self.exposed.entry((from, weapon_type.clone())).or_insert_with(|| {
+            let bullet_size = properties.weapon_params[weapon_type].bullet.size;
+            let muzzle = Vec2F64 { x: from.0 as f64 + 0.5, y: from.1 as f64 + properties.unit_size.y / 2.0 };
+            let mut row = vec![false; width * height];
+            for x in 0..width {
+                for y in 0..height {
+                    let index = x * height + y;
+                    if !standing[index] {
+                        continue;
+                    }
+                    let position = Vec2F64 { x: x as f64 + 0.5, y: y as f64 };
+                    row[index] = Visibility::unit_visible(muzzle, position, properties.unit_size, bullet_size, level);
+                }
+            }
+            row
+        })
+    }
+
+    fn index(&self, pos: TilePos) -> Option<usize> {
+        if pos.0 < 0 || pos.1 < 0 || pos.0 >= self.width as isize || pos.1 >= self.height as isize {
+            None
+        } else {
+            Some(pos.0 as usize * self.height + pos.1 as usize)
+        }
+    }
+}
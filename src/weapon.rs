@@ -0,0 +1,95 @@
+use model::{Game, Item, Properties, Unit, Vec2F64, WeaponParams, WeaponType};
+
+use crate::visibility::Visibility;
+
+// своя ракета вблизи задевает и нас
+const SELF_SPLASH_FACTOR: f64 = 0.5;
+// меняем оружие, только если новое заметно лучше, чтобы не менять туда-обратно
+const SWAP_MARGIN: f64 = 1.2;
+
+/// Ожидаемый урон в секунду по противнику на расстоянии distance при непрерывной стрельбе.
+/// Разброс между выстрелами растет на recoil и успевает уменьшиться на aim_speed * fire_rate,
+/// вероятность попадания - доля отклонения, при которой пуля (или взрыв) еще задевает противника.
+pub fn weapon_dps(params: &WeaponParams, distance: f64, properties: &Properties) -> f64 {
+    let spread = (params.min_spread + params.recoil - params.aim_speed * params.fire_rate)
+        .max(params.min_spread)
+        .min(params.max_spread);
+    let miss = distance * spread.tan();
+    let half_target = (properties.unit_size.x + properties.unit_size.y) / 4.0;
+    let hit_chance = |half_size: f64| if miss <= half_size { 1.0 } else { half_size / miss };
+    let bullet_damage = params.bullet.damage as f64 * hit_chance(half_target + params.bullet.size / 2.0);
+    let explosion_damage = params.explosion.as_ref()
+        .map(|explosion| explosion.damage as f64 * hit_chance(half_target + explosion.radius))
+        .unwrap_or(0.0);
+    // среднее время на выстрел с учетом перезарядки магазина
+    let shot_time = (params.magazine_size as f64 * params.fire_rate + params.reload_time) / params.magazine_size as f64;
+    (bullet_damage + explosion_damage) / shot_time
+}
+
+/// Оценка оружия против противников: DPS по ближайшему с поправкой на взрыв рядом с собой.
+/// Против ракетницы держимся дальше радиуса ее взрыва, поэтому и оцениваем на этом расстоянии.
+pub fn weapon_value(weapon_type: &WeaponType, unit: &Unit, game: &Game) -> f64 {
+    let properties = &game.properties;
+    let params = &properties.weapon_params[weapon_type];
+    let enemies = game.units.iter().filter(|unit2| unit2.player_id != unit.player_id);
+    let mut distance = enemies.clone()
+        .map(|unit2| ((unit2.position.x - unit.position.x).powi(2) + (unit2.position.y - unit.position.y).powi(2)).sqrt())
+        .fold(f64::INFINITY, f64::min);
+    if distance.is_infinite() {
+        distance = 0.0;
+    }
+    for enemy in enemies {
+        if let Some(explosion) = enemy.weapon.as_ref().and_then(|weapon| weapon.params.explosion.as_ref()) {
+            distance = distance.max(explosion.radius + properties.unit_size.y);
+        }
+    }
+    let dps = weapon_dps(params, distance, properties);
+    match &params.explosion {
+        Some(explosion) if distance < explosion.radius + properties.unit_size.x => dps * SELF_SPLASH_FACTOR,
+        _ => dps,
+    }
+}
+
+/// оружие, на которое стоит поменять свое (заметно лучше текущего)
+pub fn is_weapon_upgrade(weapon_type: &WeaponType, unit: &Unit, game: &Game) -> bool {
+    match &unit.weapon {
+        Some(weapon) => weapon.typ != *weapon_type &&
+            weapon_value(weapon_type, unit, game) > weapon_value(&weapon.typ, unit, game) * SWAP_MARGIN,
+        None => true,
+    }
+}
+
+/// стоим на ящике с оружием лучше нашего - меняем
+pub fn should_swap_weapon(unit: &Unit, game: &Game) -> bool {
+    unit.weapon.is_some() && game.loot_boxes.iter().any(|loot| {
+        let touches = (loot.position.x - unit.position.x).abs() <= (loot.size.x + unit.size.x) / 2.0 &&
+            (loot.position.y + loot.size.y / 2.0 - unit.position.y - unit.size.y / 2.0).abs() <= (loot.size.y + unit.size.y) / 2.0;
+        match &loot.item {
+            Item::Weapon { weapon_type } => touches && is_weapon_upgrade(weapon_type, unit, game),
+            _ => false,
+        }
+    })
+}
+
+/// Тактическая перезарядка неполного магазина: когда противники нас не видят или когда отступаем
+/// (все равно не стреляем). Пока идет перезарядка, мы не можем отвечать: планировщик штрафует пути за тики перезарядки на простреливаемых клетках.
+pub fn should_reload(unit: &Unit, game: &Game, retreating: bool) -> bool {
+    let weapon = match &unit.weapon {
+        Some(weapon) => weapon,
+        None => return false,
+    };
+    if weapon.magazine >= weapon.params.magazine_size {
+        return false;
+    }
+    // уже перезаряжаемся
+    if weapon.fire_timer.unwrap_or(0.0) > weapon.params.fire_rate {
+        return false;
+    }
+    if retreating {
+        return true;
+    }
+    let muzzle = unit.position.add(Vec2F64 { x: 0.0, y: unit.size.y / 2.0 });
+    !game.units.iter()
+        .filter(|unit2| unit2.player_id != unit.player_id)
+        .any(|unit2| Visibility::unit_visible(muzzle, unit2.position, unit2.size, weapon.params.bullet.size, &game.level))
+}